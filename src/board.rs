@@ -0,0 +1,304 @@
+//! A thread-safe coat check for job results.
+//!
+//! A `ResultBoard<V>` hands out a `Ticket` and a `Promise<V>` for each job. The submitter keeps the
+//! ticket and waits for the result (blocking, or through a `Future`), the worker fulfills the
+//! promise. If the promise is dropped instead (e.g., because the worker panicked), the wait ends
+//! with `ErrorKind::Abandoned`.
+use std::collections::HashMap;
+use std::error::Error as ErrorTrait;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use {CoatCheck, Deposit, Ticket, TicketId, ClaimError, ErrorKind};
+
+struct State<V> {
+    /// The results (`None` if abandoned).
+    cc: CoatCheck<Option<V>>,
+    /// Tasks waiting for pending results.
+    wakers: HashMap<TicketId, Waker>,
+    /// Tickets for pending results whose `Claim` futures were dropped. These results are dropped
+    /// as soon as they're posted.
+    abandoned: HashMap<TicketId, Ticket>,
+}
+
+/// A coat check for results that haven't been computed yet.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use coatcheck::ResultBoard;
+///
+/// let board = Arc::new(ResultBoard::new());
+/// let (ticket, promise) = board.reserve_slot();
+///
+/// let handle = thread::spawn(move || {
+///     promise.fill(6 * 7);
+/// });
+///
+/// assert_eq!(board.wait(ticket).unwrap(), 42);
+/// handle.join().unwrap();
+/// ```
+pub struct ResultBoard<V> {
+    state: Mutex<State<V>>,
+    filled: Condvar,
+}
+
+impl<V> ResultBoard<V> {
+    /// Constructs a new, empty `ResultBoard<V>`.
+    #[inline]
+    pub fn new() -> Self {
+        ResultBoard {
+            state: Mutex::new(State {
+                cc: CoatCheck::new(),
+                wakers: HashMap::new(),
+                abandoned: HashMap::new(),
+            }),
+            filled: Condvar::new(),
+        }
+    }
+
+    /// The number of results, including those that are still pending.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lock().cc.len()
+    }
+
+    /// Check if this `ResultBoard<V>` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reserve a slot for a result.
+    ///
+    /// Keep the ticket and hand the promise to whoever computes the result.
+    ///
+    /// *Note:* Dropping the ticket leaks the result once it's posted. To give up on a result, drop
+    /// the future returned by `ResultBoard::claim_async` instead.
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn reserve_slot(self: &Arc<Self>) -> (Ticket, Promise<V>) {
+        let (ticket, deposit) = self.lock().cc.reserve_slot();
+        (ticket, Promise { board: self.clone(), deposit: Some(deposit) })
+    }
+
+    /// Claim a result if it's ready.
+    ///
+    /// Returns `Ok(value)` if the result has been posted.
+    /// Returns `Err(WaitError)` with `ErrorKind::Pending` if the result is still pending,
+    /// `ErrorKind::WrongCoatCheck` if the ticket belongs to another `ResultBoard<V>` (returning the
+    /// ticket inside of the WaitError in both cases), or `ErrorKind::Abandoned` if the promise was
+    /// dropped (eating the ticket).
+    #[inline]
+    pub fn try_claim(&self, ticket: Ticket) -> Result<V, WaitError> {
+        finish(self.lock().cc.claim(ticket))
+    }
+
+    /// Block until a result is posted (or abandoned) and claim it.
+    ///
+    /// See `ResultBoard::try_claim` for the errors (except `ErrorKind::Pending`).
+    pub fn wait(&self, mut ticket: Ticket) -> Result<V, WaitError> {
+        let mut state = self.lock();
+        loop {
+            match state.cc.claim(ticket) {
+                Err(ClaimError { kind: ErrorKind::Pending, ticket: t }) => ticket = t,
+                result => return finish(result),
+            }
+            state = self.filled.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Claim a result once it's posted (or abandoned), without blocking.
+    ///
+    /// The returned future resolves to the same thing `wait` returns. It works with any executor
+    /// and doesn't borrow the board (so it can be spawned).
+    ///
+    /// *Note:* Dropping the future gives up on the result: it's dropped (and its slot freed) as
+    /// soon as it's posted.
+    #[inline]
+    pub fn claim_async(self: &Arc<Self>, ticket: Ticket) -> Claim<V> {
+        Claim { board: self.clone(), ticket: Some(ticket) }
+    }
+
+    /// Fill a reserved slot (`None` if the promise was abandoned) and wake anyone waiting for it.
+    ///
+    /// If the waiter has given up, the value is dropped and the slot freed instead.
+    fn post(&self, deposit: Deposit, value: Option<V>) {
+        let id = deposit.id();
+        // The unclaimed value is dropped after the lock is released.
+        let (waker, _unclaimed) = {
+            let mut state = self.lock();
+            // Promises can't be moved between boards and boards are never cleared.
+            deposit.fill(&mut state.cc, value).ok().expect("promise for another board");
+            let unclaimed = state.abandoned.remove(&id).map(|ticket| {
+                state.cc.claim(ticket).ok().expect("slot was just filled")
+            });
+            (state.wakers.remove(&id), unclaimed)
+        };
+        self.filled.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Lock the state.
+    ///
+    /// A panic while the lock is held can't leave the coat check in an inconsistent state so the
+    /// poison is ignored.
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, State<V>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Turn the result of claiming a slot into the result of a wait.
+#[inline]
+fn finish<V>(result: Result<Option<V>, ClaimError>) -> Result<V, WaitError> {
+    match result {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) => Err(WaitError { kind: ErrorKind::Abandoned, ticket: None }),
+        Err(e) => Err(WaitError { kind: e.kind, ticket: Some(e.ticket) }),
+    }
+}
+
+impl<V> fmt::Debug for ResultBoard<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ResultBoard({})", self.len())
+    }
+}
+
+impl<V> Default for ResultBoard<V> {
+    #[inline]
+    fn default() -> Self {
+        ResultBoard::new()
+    }
+}
+
+/// A promise to post a result to a `ResultBoard<V>` (see `ResultBoard::reserve_slot`).
+///
+/// Dropping the promise without filling it abandons the result.
+#[must_use = "dropping the promise abandons the result"]
+pub struct Promise<V> {
+    board: Arc<ResultBoard<V>>,
+    /// `None` once filled.
+    deposit: Option<Deposit>,
+}
+
+impl<V> Promise<V> {
+    /// Get the id of the ticket issued along with this promise.
+    #[inline]
+    pub fn id(&self) -> TicketId {
+        self.deposit.as_ref().expect("promise already filled").id()
+    }
+
+    /// Post the result, waking anyone waiting for it.
+    #[inline]
+    pub fn fill(mut self, value: V) {
+        let deposit = self.deposit.take().expect("promise already filled");
+        self.board.post(deposit, Some(value));
+    }
+}
+
+impl<V> Drop for Promise<V> {
+    fn drop(&mut self) {
+        if let Some(deposit) = self.deposit.take() {
+            self.board.post(deposit, None);
+        }
+    }
+}
+
+impl<V> fmt::Debug for Promise<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Promise")
+    }
+}
+
+/// The error yielded when claiming a result from a `ResultBoard<V>` fails.
+pub struct WaitError {
+    /// The error kind.
+    pub kind: ErrorKind,
+    /// The ticket (`None` if the result was abandoned, which uses the ticket up).
+    pub ticket: Option<Ticket>,
+}
+
+impl WaitError {
+    /// Get the ticket back (if it wasn't used up).
+    #[inline]
+    pub fn into_ticket(self) -> Option<Ticket> {
+        self.ticket
+    }
+}
+
+impl ErrorTrait for WaitError {
+    fn description(&self) -> &str {
+        self.kind.description()
+    }
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WaitError: {}", self.kind.description())
+    }
+}
+
+impl fmt::Debug for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// A future claiming a result from a `ResultBoard<V>` (see `ResultBoard::claim_async`).
+#[must_use = "futures do nothing unless polled"]
+pub struct Claim<V> {
+    board: Arc<ResultBoard<V>>,
+    /// `None` once the future has completed.
+    ticket: Option<Ticket>,
+}
+
+impl<V> Future for Claim<V> {
+    type Output = Result<V, WaitError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let ticket = this.ticket.take().expect("polled after completion");
+        let mut state = this.board.lock();
+        match state.cc.claim(ticket) {
+            Err(ClaimError { kind: ErrorKind::Pending, ticket }) => {
+                state.wakers.insert(ticket.id(), cx.waker().clone());
+                this.ticket = Some(ticket);
+                Poll::Pending
+            },
+            result => Poll::Ready(finish(result)),
+        }
+    }
+}
+
+/// Gives up on the result, dropping it now if it has been posted or once it is.
+impl<V> Drop for Claim<V> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            // The posted value is dropped after the lock is released.
+            let _posted = {
+                let mut state = self.board.lock();
+                state.wakers.remove(&ticket.id());
+                match state.cc.claim(ticket) {
+                    Err(ClaimError { kind: ErrorKind::Pending, ticket }) => {
+                        state.abandoned.insert(ticket.id(), ticket);
+                        None
+                    },
+                    result => result.ok(),
+                }
+            };
+        }
+    }
+}
+
+impl<V> fmt::Debug for Claim<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Claim")
+    }
+}
@@ -0,0 +1,200 @@
+//! Branded coat checks.
+//!
+//! A `BrandedCoatCheck<'id, V>` only exists inside of a `CoatCheck::scope` closure and is branded
+//! with a unique, invariant lifetime (`'id`). Its tickets carry the same brand so the compiler
+//! (instead of a runtime tag comparison) ensures that they're only ever used with the coat check
+//! that issued them.
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+
+use {CoatCheck, Entry, Iter, IterMut, Walk};
+use Entry::*;
+
+/// An invariant lifetime brand.
+type Brand<'id> = PhantomData<Cell<&'id mut ()>>;
+
+/// A ticket issued by a `BrandedCoatCheck<'id, V>`.
+///
+/// Unlike a `Ticket`, a `BrandedTicket` doesn't carry a tag; it's just an index.
+#[allow(missing_copy_implementations)]
+#[must_use = "you need this ticket to claim your item"]
+pub struct BrandedTicket<'id> {
+    index: usize,
+    _brand: Brand<'id>,
+}
+
+impl<'id> fmt::Debug for BrandedTicket<'id> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "BrandedTicket")
+    }
+}
+
+/// A coat check that can only be used with tickets that it issued (see `CoatCheck::scope`).
+///
+/// All lookups are infallible.
+pub struct BrandedCoatCheck<'id, V> {
+    data: Vec<Entry<V>>,
+    size: usize,
+    next_free: usize,
+    _brand: Brand<'id>,
+}
+
+impl<V> CoatCheck<V> {
+    /// Run `f` with a new, empty, branded coat check.
+    ///
+    /// The coat check and its tickets can't escape `f` and tickets from other scopes can't be
+    /// used with it. As a matter of fact, using a ticket from the wrong scope won't even compile:
+    ///
+    /// ```compile_fail
+    /// use coatcheck::CoatCheck;
+    ///
+    /// CoatCheck::scope(|mut a| {
+    ///     CoatCheck::scope(|b: coatcheck::BrandedCoatCheck<i32>| {
+    ///         let ticket = a.check(1);
+    ///         b[&ticket];
+    ///     })
+    /// });
+    /// ```
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let total = CoatCheck::scope(|mut cc| {
+    ///     let t1 = cc.check(1);
+    ///     let t2 = cc.check(2);
+    ///     cc[&t1] += 10;
+    ///     cc[&t1] + cc.claim(t2)
+    /// });
+    /// assert_eq!(total, 13);
+    /// ```
+    #[inline]
+    pub fn scope<F, R>(f: F) -> R where F: for<'id> FnOnce(BrandedCoatCheck<'id, V>) -> R {
+        f(BrandedCoatCheck {
+            data: Vec::new(),
+            size: 0,
+            next_free: 0,
+            _brand: PhantomData,
+        })
+    }
+}
+
+impl<'id, V> BrandedCoatCheck<'id, V> {
+    /// Returns the number of elements the coat check can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// The number of checked items.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Check if this `BrandedCoatCheck<'id, V>` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Reserves capacity for at least `additional` more elements to be checked in.
+    ///
+    /// See `CoatCheck::reserve` for details.
+    pub fn reserve(&mut self, additional: usize) {
+        let extra_space = self.data.len() - self.len();
+        if extra_space < additional {
+            self.data.reserve(additional - extra_space)
+        }
+    }
+
+    /// Check a value in and get a `BrandedTicket` in exchange.
+    ///
+    /// *Panics* if the size of the `BrandedCoatCheck<'id, V>` would overflow `usize::MAX`.
+    pub fn check(&mut self, value: V) -> BrandedTicket<'id> {
+        let loc = self.next_free;
+        debug_assert!(loc <= self.data.len());
+
+        self.next_free = if self.next_free == self.data.len() {
+            self.data.push(Full(value));
+            self.next_free.checked_add(1).unwrap()
+        } else {
+            // Safe because we've recorded that it is safe.
+            unsafe { self.data.get_unchecked_mut(loc) }.fill(Full(value))
+        };
+        self.size += 1;
+        BrandedTicket { index: loc, _brand: PhantomData }
+    }
+
+    /// Claim an item.
+    #[inline]
+    pub fn claim(&mut self, ticket: BrandedTicket<'id>) -> V {
+        // Safe because, if we've handed out the ticket, this slot must exist.
+        let value = unsafe { self.data.get_unchecked_mut(ticket.index) }.empty(self.next_free);
+        self.next_free = ticket.index;
+        self.size -= 1;
+        value
+    }
+
+    /// Get a reference to the value matching this ticket.
+    #[inline]
+    pub fn get(&self, ticket: &BrandedTicket<'id>) -> &V {
+        // Safe because, if we've handed out the ticket, this slot must exist.
+        match unsafe { self.data.get_unchecked(ticket.index) } {
+            &Full(ref v) => v,
+            _ => panic!("forged ticket"),
+        }
+    }
+
+    /// Get a mutable reference to the value matching this ticket.
+    #[inline]
+    pub fn get_mut(&mut self, ticket: &BrandedTicket<'id>) -> &mut V {
+        // Safe because, if we've handed out the ticket, this slot must exist.
+        match unsafe { self.data.get_unchecked_mut(ticket.index) } {
+            &mut Full(ref mut v) => v,
+            _ => panic!("forged ticket"),
+        }
+    }
+
+    /// Iterate over the items in this `BrandedCoatCheck<'id, V>`.
+    #[inline]
+    pub fn iter<'a>(&'a self) -> Iter<'a, V> {
+        Iter { walk: Walk::new(self.data.iter(), (), self.len()) }
+    }
+
+    /// Mutably iterate over the items in this `BrandedCoatCheck<'id, V>`.
+    #[inline]
+    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, V> {
+        let remaining = self.len();
+        IterMut { walk: Walk::new(self.data.iter_mut(), (), remaining) }
+    }
+}
+
+impl<'id, V> fmt::Debug for BrandedCoatCheck<'id, V> where V: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, v) in self.iter().enumerate() {
+            if i != 0 { write!(f, ", ")?; }
+            write!(f, "{:?}", *v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<'a, 'id, V> Index<&'a BrandedTicket<'id>> for BrandedCoatCheck<'id, V> {
+    type Output = V;
+    #[inline]
+    fn index(&self, ticket: &BrandedTicket<'id>) -> &V {
+        self.get(ticket)
+    }
+}
+
+impl<'a, 'id, V> IndexMut<&'a BrandedTicket<'id>> for BrandedCoatCheck<'id, V> {
+    #[inline]
+    fn index_mut(&mut self, ticket: &BrandedTicket<'id>) -> &mut V {
+        self.get_mut(ticket)
+    }
+}
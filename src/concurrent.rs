@@ -0,0 +1,311 @@
+//! A coat check that can be shared between threads.
+//!
+//! Instead of a single vector, a `ConcurrentCoatCheck<V>` stores its slots in a fixed table of
+//! lazily allocated buckets (each twice the size of the previous one) so slots never move once
+//! allocated. Free slots are kept on a lock-free (Treiber) stack. Ticket uniqueness guarantees that
+//! only the ticket holder can access a slot so the values themselves need no synchronization.
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+use snowflake::ProcessUniqueId;
+
+use {Ticket, ClaimError, AccessError, ErrorKind};
+
+/// log2 of the size of the first bucket.
+const FIRST_BUCKET_BITS: usize = 5;
+
+/// The number of buckets. Enough to index `2^32 - 1` slots.
+const BUCKETS: usize = 32 - FIRST_BUCKET_BITS;
+
+/// The largest number of slots a `ConcurrentCoatCheck` can hold (the last bucket is cut short).
+const MAX_SLOTS: usize = NONE as usize - (1 << FIRST_BUCKET_BITS);
+
+/// Marks the end of the free list.
+const NONE: u32 = !0;
+
+/// Marks a full slot (out of the range of `u32` indices, even on 32-bit targets).
+const FULL: u64 = !0;
+
+struct Slot<V> {
+    /// `FULL` if the slot is full, otherwise the next free index (or `NONE`).
+    next: AtomicU64,
+    value: UnsafeCell<mem::MaybeUninit<V>>,
+}
+
+/// Map an index to a bucket and an offset into that bucket.
+#[inline]
+fn locate(index: usize) -> (usize, usize) {
+    let shifted = index + (1 << FIRST_BUCKET_BITS);
+    let high_bit = (mem::size_of::<usize>() * 8 - 1) - shifted.leading_zeros() as usize;
+    (high_bit - FIRST_BUCKET_BITS, shifted ^ (1 << high_bit))
+}
+
+#[inline]
+fn bucket_size(bucket: usize) -> usize {
+    1 << (bucket + FIRST_BUCKET_BITS)
+}
+
+/// Pack a free list head (index plus ABA counter).
+#[inline]
+fn pack(index: u32, counter: u32) -> u64 {
+    ((counter as u64) << 32) | index as u64
+}
+
+#[inline]
+fn unpack(head: u64) -> (u32, u32) {
+    (head as u32, (head >> 32) as u32)
+}
+
+/// A coat check whose `check`, `claim`, and `get` methods can be called concurrently from multiple
+/// threads (through `&self`).
+///
+/// Unlike `CoatCheck::get`, `ConcurrentCoatCheck::get` borrows the ticket for as long as the
+/// returned reference lives so the value can't be claimed out from under the reference. For the
+/// same reason, `ConcurrentCoatCheck` doesn't implement `Index`.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use coatcheck::ConcurrentCoatCheck;
+///
+/// let cc = Arc::new(ConcurrentCoatCheck::new());
+///
+/// let handles: Vec<_> = (0..4).map(|i| {
+///     let cc = cc.clone();
+///     thread::spawn(move || cc.check(i))
+/// }).collect();
+///
+/// let tickets: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+/// assert_eq!(cc.len(), 4);
+///
+/// let sum: i32 = tickets.into_iter().map(|t| cc.claim(t).unwrap()).sum();
+/// assert_eq!(sum, 6);
+/// assert!(cc.is_empty());
+/// ```
+pub struct ConcurrentCoatCheck<V> {
+    tag: ProcessUniqueId,
+    buckets: [AtomicPtr<Slot<V>>; BUCKETS],
+    /// Head of the free list.
+    free: AtomicU64,
+    /// Number of slots ever handed out (the high water mark).
+    allocated: AtomicUsize,
+    size: AtomicUsize,
+    _marker: PhantomData<V>,
+}
+
+unsafe impl<V> Send for ConcurrentCoatCheck<V> where V: Send {}
+unsafe impl<V> Sync for ConcurrentCoatCheck<V> where V: Send + Sync {}
+
+impl<V> ConcurrentCoatCheck<V> {
+    /// Constructs a new, empty `ConcurrentCoatCheck<T>`.
+    ///
+    /// The coat check will not allocate until elements are checked into it.
+    pub fn new() -> Self {
+        ConcurrentCoatCheck {
+            tag: ProcessUniqueId::new(),
+            buckets: Default::default(),
+            free: AtomicU64::new(pack(NONE, 0)),
+            allocated: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of checked items.
+    ///
+    /// This is only a snapshot; other threads may check and claim items concurrently.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Check if this `ConcurrentCoatCheck<V>` is empty.
+    ///
+    /// This is only a snapshot; other threads may check and claim items concurrently.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check if a ticket belongs to this `ConcurrentCoatCheck<V>`.
+    #[inline]
+    pub fn contains_ticket(&self, ticket: &Ticket) -> bool {
+        ticket.tag == self.tag
+    }
+
+    /// Get the slot at `index`.
+    ///
+    /// Unsafe because the slot's bucket must have been allocated.
+    #[inline]
+    unsafe fn slot(&self, index: usize) -> &Slot<V> {
+        let (bucket, offset) = locate(index);
+        let ptr = self.buckets.get_unchecked(bucket).load(Ordering::Acquire);
+        debug_assert!(!ptr.is_null());
+        &*ptr.add(offset)
+    }
+
+    /// Make sure the bucket containing `index` is allocated.
+    fn allocate(&self, index: usize) {
+        let (bucket, _) = locate(index);
+        let bucket_ptr = &self.buckets[bucket];
+        if !bucket_ptr.load(Ordering::Acquire).is_null() {
+            return;
+        }
+        let slots: Vec<Slot<V>> = (0..bucket_size(bucket)).map(|_| Slot {
+            next: AtomicU64::new(NONE as u64),
+            value: UnsafeCell::new(mem::MaybeUninit::uninit()),
+        }).collect();
+        let new = Box::into_raw(slots.into_boxed_slice()) as *mut Slot<V>;
+        if bucket_ptr.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            // Someone beat us to it.
+            unsafe { drop(Box::from_raw(ptr::slice_from_raw_parts_mut(new, bucket_size(bucket)))) };
+        }
+    }
+
+    /// Find a free slot, allocating one if necessary.
+    fn acquire(&self) -> usize {
+        let mut head = self.free.load(Ordering::Acquire);
+        loop {
+            let (index, counter) = unpack(head);
+            if index == NONE {
+                break;
+            }
+            // Safe because free slots have been allocated. The slot may have been taken by
+            // another thread in the meantime but then the counter will have changed and the
+            // exchange below will fail.
+            let next = unsafe { self.slot(index as usize) }.next.load(Ordering::Relaxed);
+            let new = pack(next as u32, counter.wrapping_add(1));
+            match self.free.compare_exchange_weak(head, new, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return index as usize,
+                Err(actual) => head = actual,
+            }
+        }
+        let index = self.allocated.fetch_add(1, Ordering::Relaxed);
+        if index >= MAX_SLOTS {
+            self.allocated.fetch_sub(1, Ordering::Relaxed);
+            panic!("ConcurrentCoatCheck is full");
+        }
+        self.allocate(index);
+        index
+    }
+
+    /// Put a slot back on the free list.
+    fn release(&self, index: usize, slot: &Slot<V>) {
+        let mut head = self.free.load(Ordering::Relaxed);
+        loop {
+            let (next, counter) = unpack(head);
+            slot.next.store(next as u64, Ordering::Relaxed);
+            let new = pack(index as u32, counter.wrapping_add(1));
+            match self.free.compare_exchange_weak(head, new, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Check a value in and get a `Ticket` in exchange.
+    ///
+    /// *Panics* if the coat check would hold more than `2^32 - 33` items.
+    pub fn check(&self, value: V) -> Ticket {
+        let index = self.acquire();
+        // Safe because `acquire` hands out every free slot to exactly one thread.
+        unsafe {
+            let slot = self.slot(index);
+            (*slot.value.get()) = mem::MaybeUninit::new(value);
+            slot.next.store(FULL, Ordering::Release);
+        }
+        self.size.fetch_add(1, Ordering::Relaxed);
+        Ticket { tag: self.tag, epoch: 0, index: index }
+    }
+
+    /// Claim an item.
+    ///
+    /// Returns `Ok(value)` if the ticket belongs to this `ConcurrentCoatCheck<V>` (eating the
+    /// ticket).
+    /// Returns `Err(ClaimError)` if the ticket belongs to another coat check (returning the ticket
+    /// inside of the ClaimError).
+    pub fn claim(&self, ticket: Ticket) -> Result<V, ClaimError> {
+        match ticket {
+            Ticket { tag, index, .. } if tag == self.tag => {
+                // Safe because, if we've handed out the ticket, this slot must be full and nobody
+                // else can access it.
+                let value = unsafe {
+                    let slot = self.slot(index);
+                    debug_assert_eq!(slot.next.load(Ordering::Relaxed), FULL);
+                    let value = ptr::read((*slot.value.get()).as_ptr());
+                    self.release(index, slot);
+                    value
+                };
+                self.size.fetch_sub(1, Ordering::Relaxed);
+                ticket.consume();
+                Ok(value)
+            },
+            _ => Err(ClaimError { ticket: ticket, kind: ErrorKind::WrongCoatCheck })
+        }
+    }
+
+    /// Get a reference to the value matching this ticket.
+    ///
+    /// The ticket stays borrowed for as long as the reference lives.
+    ///
+    /// Returns `Ok(&value)` if the ticket belongs to this `ConcurrentCoatCheck<V>`.
+    /// Returns `Err(AccessError)` if the ticket belongs to another coat check.
+    pub fn get<'a>(&'a self, ticket: &'a Ticket) -> Result<&'a V, AccessError> {
+        if ticket.tag == self.tag {
+            // Safe because, if we've handed out the ticket, this slot must be full and it can't
+            // be claimed while the ticket is borrowed.
+            Ok(unsafe { &*(*self.slot(ticket.index).value.get()).as_ptr() })
+        } else {
+            Err(AccessError { kind: ErrorKind::WrongCoatCheck })
+        }
+    }
+
+    /// Get a mutable reference to the value matching this ticket.
+    ///
+    /// Holding the ticket mutably guarantees exclusive access to the value so this only needs a
+    /// shared reference to the coat check.
+    ///
+    /// Returns `Ok(&mut value)` if the ticket belongs to this `ConcurrentCoatCheck<V>`.
+    /// Returns `Err(AccessError)` if the ticket belongs to another coat check.
+    pub fn get_mut<'a>(&'a self, ticket: &'a mut Ticket) -> Result<&'a mut V, AccessError> {
+        if ticket.tag == self.tag {
+            // Safe because, if we've handed out the ticket, this slot must be full and nobody
+            // else can hold a reference to the ticket.
+            Ok(unsafe { &mut *(*self.slot(ticket.index).value.get()).as_mut_ptr() })
+        } else {
+            Err(AccessError { kind: ErrorKind::WrongCoatCheck })
+        }
+    }
+}
+
+impl<V> Drop for ConcurrentCoatCheck<V> {
+    fn drop(&mut self) {
+        for (bucket, ptr) in self.buckets.iter_mut().enumerate() {
+            let ptr = *ptr.get_mut();
+            if ptr.is_null() {
+                continue;
+            }
+            // Safe because we allocated this bucket with exactly this size and own its values.
+            unsafe {
+                let mut slots = Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, bucket_size(bucket)));
+                for slot in slots.iter_mut() {
+                    if *slot.next.get_mut() == FULL {
+                        ptr::drop_in_place((*slot.value.get()).as_mut_ptr());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<V> Default for ConcurrentCoatCheck<V> {
+    #[inline]
+    fn default() -> Self {
+        ConcurrentCoatCheck::new()
+    }
+}
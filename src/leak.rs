@@ -0,0 +1,137 @@
+//! Leak tracking (enabled by the `leak-check` feature).
+//!
+//! With the feature enabled, every coat check records where each of its values was checked in and
+//! dropped tickets report themselves to a global registry (keyed by coat check tag) so that the
+//! coat check can find slots that can no longer be claimed. Without the feature, everything in
+//! this module compiles down to nothing.
+#[cfg(feature = "leak-check")]
+pub use self::enabled::*;
+#[cfg(not(feature = "leak-check"))]
+pub use self::disabled::*;
+
+#[cfg(feature = "leak-check")]
+mod enabled {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::panic::Location;
+    use std::sync::{Mutex, MutexGuard};
+
+    use snowflake::ProcessUniqueId;
+
+    /// The location at which a value was checked in.
+    pub type Caller = &'static Location<'static>;
+
+    /// Tickets dropped without being turned in (epoch, index), by coat check.
+    static DROPPED: Mutex<BTreeMap<ProcessUniqueId, Vec<(u64, usize)>>> = Mutex::new(BTreeMap::new());
+
+    fn dropped() -> MutexGuard<'static, BTreeMap<ProcessUniqueId, Vec<(u64, usize)>>> {
+        // A panic while holding the lock can't leave the map in an inconsistent state.
+        DROPPED.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    #[track_caller]
+    pub fn caller() -> Caller {
+        Location::caller()
+    }
+
+    /// Start tracking dropped tickets for a coat check.
+    pub fn register(tag: ProcessUniqueId) {
+        dropped().insert(tag, Vec::new());
+    }
+
+    /// Stop tracking dropped tickets for a coat check.
+    pub fn unregister(tag: ProcessUniqueId) {
+        dropped().remove(&tag);
+    }
+
+    /// Forget dropped tickets from previous epochs.
+    pub fn expire(tag: ProcessUniqueId, epoch: u64) {
+        if let Some(tickets) = dropped().get_mut(&tag) {
+            tickets.retain(|&(e, _)| e == epoch);
+        }
+    }
+
+    /// Record a dropped ticket (if its coat check is being tracked).
+    pub fn record(tag: ProcessUniqueId, epoch: u64, index: usize) {
+        if let Some(tickets) = dropped().get_mut(&tag) {
+            tickets.push((epoch, index));
+        }
+    }
+
+    /// The indices of the slots whose tickets have been dropped in the given epoch.
+    pub fn leaked(tag: ProcessUniqueId, epoch: u64) -> Vec<usize> {
+        dropped().get(&tag).map(|tickets| {
+            tickets.iter().filter(|&&(e, _)| e == epoch).map(|&(_, i)| i).collect()
+        }).unwrap_or_default()
+    }
+
+    /// Where each slot's value was checked in.
+    #[derive(Default)]
+    pub struct Locations(Vec<Option<Caller>>);
+
+    impl Locations {
+        #[inline]
+        pub fn record(&mut self, index: usize, caller: Caller) {
+            if index >= self.0.len() {
+                self.0.resize(index + 1, None);
+            }
+            self.0[index] = Some(caller);
+        }
+
+        #[inline]
+        pub fn get(&self, index: usize) -> Option<Caller> {
+            self.0.get(index).and_then(|l| *l)
+        }
+
+        #[inline]
+        pub fn clear(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    /// A slot that can no longer be claimed because its ticket was dropped.
+    #[derive(Clone, Copy)]
+    pub struct Leak {
+        /// The index of the leaked slot.
+        pub index: usize,
+        /// Where the leaked value was checked in (if known).
+        pub location: Option<Caller>,
+    }
+
+    impl fmt::Debug for Leak {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.location {
+                Some(location) => write!(f, "slot {} (checked at {})", self.index, location),
+                None => write!(f, "slot {}", self.index),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "leak-check"))]
+mod disabled {
+    use snowflake::ProcessUniqueId;
+
+    pub type Caller = ();
+
+    #[inline(always)]
+    pub fn caller() -> Caller {}
+
+    #[inline(always)]
+    pub fn register(_tag: ProcessUniqueId) {}
+
+    #[inline(always)]
+    pub fn expire(_tag: ProcessUniqueId, _epoch: u64) {}
+
+    #[derive(Default)]
+    pub struct Locations;
+
+    impl Locations {
+        #[inline(always)]
+        pub fn record(&mut self, _index: usize, _caller: Caller) {}
+
+        #[inline(always)]
+        pub fn clear(&mut self) {}
+    }
+}
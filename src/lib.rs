@@ -103,22 +103,24 @@
 //! fact that it can choose the IDs:
 //!
 //! ```rust
-//! use coatcheck::{CoatCheck, Ticket};
+//! #[macro_use]
+//! extern crate coatcheck;
 //!
-//! struct System {
-//!     callbacks: CoatCheck<Box<FnMut() + 'static>>,
-//! }
+//! use coatcheck::CoatCheck;
 //!
-//! struct Handle {
-//!     ticket: Ticket, // Wrap it for type safety
+//! // Give the tickets their own type for type safety.
+//! define_ticket!(pub struct Handle;);
+//!
+//! struct System {
+//!     callbacks: CoatCheck<Box<FnMut() + 'static>, Handle>,
 //! }
 //!
 //! impl System {
 //!     fn add_callback<C>(&mut self, cb: C) -> Handle where C: FnMut() + 'static {
-//!         Handle { ticket: self.callbacks.check(Box::new(cb)) }
+//!         self.callbacks.check(Box::new(cb))
 //!     }
 //!     fn remove_callback(&mut self, handle: Handle) {
-//!         self.callbacks.claim(handle.ticket);
+//!         self.callbacks.claim(handle);
 //!     }
 //!     fn fire(&mut self) {
 //!         for cb in self.callbacks.iter_mut() {
@@ -126,8 +128,12 @@
 //!         }
 //!     }
 //! }
+//! # fn main() {}
 //! ```
 //!
+//! The `registry` module provides a ready-made version of this system (that also lets callbacks
+//! add and remove callbacks while it's firing).
+//!
 //! ## Discussion
 //!
 //! One thing you might note when using this library is that Tickets can't be duplicated in any way.
@@ -149,25 +155,67 @@
 //! ### Cons:
 //!
 //!  * Multiple references: There's no way to give away a reference to a value
-//!    (without using actual references, that is).
+//!    (without using actual references, that is). If you need this, use a `WeakCoatCheck` which
+//!    pays for a generation per slot to be able to hand out copyable `WeakTicket`s.
+//!
+//!  * Leaks: Dropping a ticket leaks its slot (the value can only be recovered by destroying or
+//!    clearing the coat check). To hunt these down, enable the `leak-check` feature: coat checks
+//!    will then record where each value was checked in, list the slots whose tickets have been
+//!    dropped (`CoatCheck::leaks`), and report any values still checked in when dropped.
 extern crate snowflake;
 
 use std::fmt;
 use std::vec;
-use std::ops::{Index, IndexMut};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
 use std::slice;
-use std::iter;
+use std::iter::{self, FusedIterator};
 use std::mem;
 use std::convert::From;
 use std::error::Error as ErrorTrait;
+use std::marker::PhantomData;
 
 use snowflake::ProcessUniqueId;
 
 use Entry::*;
 
+mod weak;
+mod concurrent;
+mod branded;
+mod persist;
+mod leak;
+mod shared;
+mod remap;
+mod versioned;
+mod transaction;
+mod observer;
+mod ordered;
+mod secondary;
+mod board;
+
+pub use weak::{WeakCoatCheck, WeakTicket};
+pub use concurrent::ConcurrentCoatCheck;
+pub use branded::{BrandedCoatCheck, BrandedTicket};
+pub use persist::{Snapshot, Receipt, Redeemer, RedeemError};
+pub use shared::{SharedCoatCheck, Guard};
+pub use remap::{TicketRemap, TicketMap};
+pub use versioned::{VersionedCoatCheck, Checkpoint};
+pub use transaction::{Transaction, Commit, Rollback};
+pub use observer::Observer;
+pub use ordered::{OrderedCoatCheck, OrderedIter, OrderedIterMut, OrderedIntoIter};
+pub use secondary::SecondaryMap;
+pub use board::{ResultBoard, Promise, Claim, WaitError};
+#[cfg(feature = "leak-check")]
+pub use leak::Leak;
+
+#[derive(Clone)]
 enum Entry<V> {
     Empty(usize /* next free index */),
     Full(V),
+    Lent,
+    Reserved,
+    /// Left behind by a value moved to another coat check (see `CoatCheck::split_off`). Kept off
+    /// of the free list so that the value's old tickets can't refer to a new value.
+    Moved,
 }
 
 impl<V> Entry<V> {
@@ -177,7 +225,7 @@ impl<V> Entry<V> {
     fn full(self) -> Option<V> {
         match self {
             Full(value) => Some(value),
-            Empty(_) => None
+            _ => None
         }
     }
 
@@ -208,10 +256,37 @@ impl<V> Entry<V> {
         }
     }
 
-    /// Fill an empty entry with a value and return the next free index.
+    /// Is the entry on loan
+    #[inline]
+    fn is_lent(&self) -> bool {
+        match self {
+            &Lent => true,
+            _ => false,
+        }
+    }
+
+    /// Is the entry reserved (waiting for a deposit)
+    #[inline]
+    fn is_reserved(&self) -> bool {
+        match self {
+            &Reserved => true,
+            _ => false,
+        }
+    }
+
+    /// Has the entry's value been moved to another coat check
     #[inline]
-    fn fill(&mut self, value: V) -> usize {
-        match mem::replace(self, Full(value)) {
+    fn is_moved(&self) -> bool {
+        match self {
+            &Moved => true,
+            _ => false,
+        }
+    }
+
+    /// Fill an empty entry and return the next free index.
+    #[inline]
+    fn fill(&mut self, entry: Entry<V>) -> usize {
+        match mem::replace(self, entry) {
             Empty(next_free) => next_free,
             _ => panic!("expected no entry"),
         }
@@ -235,6 +310,7 @@ impl<V> Entry<V> {
 #[must_use = "you need this ticket to claim your item"]
 pub struct Ticket {
     tag: ProcessUniqueId,
+    epoch: u64,
     index: usize,
 }
 
@@ -244,41 +320,289 @@ impl fmt::Debug for Ticket {
     }
 }
 
+impl Ticket {
+    /// Get this ticket's id.
+    #[inline]
+    pub fn id(&self) -> TicketId {
+        TicketId { tag: self.tag, epoch: self.epoch, index: self.index }
+    }
+
+    /// Convert this ticket into a `RawTicket`.
+    ///
+    /// Use `CoatCheck::redeem_raw` to turn it back into a ticket.
+    #[inline]
+    pub fn into_raw(self) -> RawTicket {
+        let raw = RawTicket { epoch: self.epoch, index: self.index };
+        self.consume();
+        raw
+    }
+
+    /// Destroy a ticket that has been turned in (so it isn't reported as leaked).
+    #[inline]
+    fn consume(self) {
+        mem::forget(self)
+    }
+
+    /// Destroy a ticket that has been turned in, returning its index.
+    #[inline]
+    fn into_index(self) -> usize {
+        let index = self.index;
+        self.consume();
+        index
+    }
+}
+
+#[cfg(feature = "leak-check")]
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        leak::record(self.tag, self.epoch, self.index);
+    }
+}
+
+/// A copyable identifier for a ticket.
+///
+/// Unlike tickets, ticket ids can be copied, compared, hashed, and used as map keys but they can't
+/// be used to access or claim values.
+///
+/// Ticket ids are only unique among outstanding tickets: once a value is claimed, every store
+/// (including `ConcurrentCoatCheck`) may give its id to the next value checked in. Remove ids from
+/// your maps when you claim their values.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TicketId {
+    tag: ProcessUniqueId,
+    epoch: u64,
+    index: usize,
+}
+
+impl TicketId {
+    /// The index of the slot this ticket id refers to.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Debug for TicketId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "TicketId({})", self.index)
+    }
+}
+
+/// A plain-old-data form of a `Ticket` (e.g., for passing through C callbacks).
+///
+/// Unlike tickets, raw tickets can be copied so it's up to you to make sure that each raw ticket is
+/// only turned back into a ticket once.
+///
+/// A raw ticket is just the coat check's epoch and the slot index (see `RawTicket::into_parts`).
+/// It doesn't record which coat check issued it so make sure to redeem it with the right one:
+/// `CoatCheck::redeem_raw` only checks that the epoch matches and that the slot is occupied.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawTicket {
+    epoch: u64,
+    index: usize,
+}
+
+impl RawTicket {
+    /// The index of the slot this raw ticket refers to.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Split this raw ticket into the coat check's epoch and the slot index.
+    #[inline]
+    pub fn into_parts(self) -> (u64, usize) {
+        (self.epoch, self.index)
+    }
+
+    /// Put a raw ticket back together from the parts returned by `RawTicket::into_parts`.
+    #[inline]
+    pub fn from_parts(epoch: u64, index: usize) -> RawTicket {
+        RawTicket { epoch: epoch, index: index }
+    }
+}
+
+impl fmt::Debug for RawTicket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "RawTicket({})", self.index)
+    }
+}
+
+/// A type that can stand in for a `Ticket`.
+///
+/// A `CoatCheck<V, T>` hands out and accepts tickets of type `T` only. Giving each kind of coat
+/// check its own ticket type turns mixing up tickets into a compile time error. You usually don't
+/// want to implement this by hand; use `define_ticket!` instead.
+///
+/// *Note:* Two coat checks sharing a ticket type still have to tell their tickets apart at runtime
+/// (failing with `ErrorKind::WrongCoatCheck`).
+pub trait TicketType: Sized {
+    /// Wrap a freshly issued ticket.
+    fn from_ticket(ticket: Ticket) -> Self;
+    /// Unwrap the ticket.
+    fn into_ticket(self) -> Ticket;
+    /// Borrow the wrapped ticket.
+    fn as_ticket(&self) -> &Ticket;
+}
+
+impl TicketType for Ticket {
+    #[inline]
+    fn from_ticket(ticket: Ticket) -> Self {
+        ticket
+    }
+    #[inline]
+    fn into_ticket(self) -> Ticket {
+        self
+    }
+    #[inline]
+    fn as_ticket(&self) -> &Ticket {
+        self
+    }
+}
+
+/// Define a new ticket type (a newtype around `Ticket` implementing `TicketType`).
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate coatcheck;
+///
+/// use coatcheck::CoatCheck;
+///
+/// define_ticket! {
+///     /// A ticket for a timer.
+///     pub struct TimerTicket;
+/// }
+///
+/// define_ticket! {
+///     /// A ticket for a socket.
+///     pub struct SocketTicket;
+/// }
+///
+/// # fn main() {
+/// let mut timers: CoatCheck<u32, TimerTicket> = CoatCheck::typed();
+/// let mut sockets: CoatCheck<&str, SocketTicket> = CoatCheck::typed();
+///
+/// let timer = timers.check(10);
+/// let socket = sockets.check("localhost:80");
+///
+/// // sockets.claim(timer); // Doesn't compile.
+/// assert_eq!(timers.claim(timer).unwrap(), 10);
+/// assert_eq!(sockets[&socket], "localhost:80");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! define_ticket {
+    ($(#[$attr:meta])* pub struct $name:ident;) => {
+        $(#[$attr])*
+        #[must_use = "you need this ticket to claim your item"]
+        pub struct $name($crate::Ticket);
+        define_ticket!(@impl $name);
+    };
+    ($(#[$attr:meta])* struct $name:ident;) => {
+        $(#[$attr])*
+        #[must_use = "you need this ticket to claim your item"]
+        struct $name($crate::Ticket);
+        define_ticket!(@impl $name);
+    };
+    (@impl $name:ident) => {
+        impl $crate::TicketType for $name {
+            #[inline]
+            fn from_ticket(ticket: $crate::Ticket) -> Self {
+                $name(ticket)
+            }
+            #[inline]
+            fn into_ticket(self) -> $crate::Ticket {
+                self.0
+            }
+            #[inline]
+            fn as_ticket(&self) -> &$crate::Ticket {
+                &self.0
+            }
+        }
+
+        impl ::std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, stringify!($name))
+            }
+        }
+
+        impl ::std::convert::From<$crate::ClaimError<$name>> for $name {
+            fn from(e: $crate::ClaimError<$name>) -> $name {
+                e.ticket
+            }
+        }
+    };
+}
+
+// Declared after `define_ticket!` so that it can use it.
+pub mod registry;
+
 /// Coat check error types
 #[derive(Clone, Copy)]
 pub enum ErrorKind {
     WrongCoatCheck,
+    Stale,
+    WrongSnapshot,
+    InvalidReceipt,
+    InvalidTicket,
+    Expired,
+    DuplicateTicket,
+    OnLoan,
+    Pending,
+    Abandoned,
+    Moved,
 }
 
 impl ErrorKind {
     pub fn description(&self) -> &str {
         match self {
-            &ErrorKind::WrongCoatCheck => "Ticket used in the wrong coat check"
+            &ErrorKind::WrongCoatCheck => "Ticket used in the wrong coat check",
+            &ErrorKind::Stale => "Ticket refers to a value that has been claimed",
+            &ErrorKind::WrongSnapshot => "Receipt redeemed against the wrong snapshot",
+            &ErrorKind::InvalidReceipt => "Receipt already redeemed or invalid",
+            &ErrorKind::InvalidTicket => "Ticket doesn't refer to a checked value",
+            &ErrorKind::Expired => "Ticket issued before the coat check was cleared",
+            &ErrorKind::DuplicateTicket => "Ticket used more than once in a single access",
+            &ErrorKind::OnLoan => "Ticket refers to a value that is on loan",
+            &ErrorKind::Pending => "Ticket refers to a slot that hasn't been filled yet",
+            &ErrorKind::Abandoned => "Ticket refers to a result that will never be posted",
+            &ErrorKind::Moved => "Ticket refers to a value that has been moved to another coat check",
         }
     }
 }
 
 /// The error yielded when a claim fails.
-pub struct ClaimError {
+pub struct ClaimError<T = Ticket> {
     /// The error kind.
     pub kind: ErrorKind,
     /// The ticket used in the failed claim.
-    pub ticket: Ticket,
+    pub ticket: T,
+}
+
+impl<T> ClaimError<T> {
+    /// Get the ticket back.
+    #[inline]
+    pub fn into_ticket(self) -> T {
+        self.ticket
+    }
 }
 
-impl ErrorTrait for ClaimError {
+impl<T> ErrorTrait for ClaimError<T> {
     fn description(&self) -> &str {
         self.kind.description()
     }
 }
 
-impl fmt::Display for ClaimError {
+impl<T> fmt::Display for ClaimError<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ClaimError: {}", self.description())
     }
 }
 
-impl fmt::Debug for ClaimError {
+impl<T> fmt::Debug for ClaimError<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(self, f)
     }
@@ -316,88 +640,378 @@ impl fmt::Debug for AccessError {
 }
 
 /// Iterator that checks-in values in exchange for tickets.
-pub struct Tickets<'a, I> where I: Iterator, <I as Iterator>::Item: 'a {
+pub struct Tickets<'a, I, T = Ticket, O = ()> where I: Iterator, <I as Iterator>::Item: 'a, T: 'a, O: 'a {
     iter: I,
-    cc: &'a mut CoatCheck<<I as Iterator>::Item>,
+    cc: &'a mut CoatCheck<<I as Iterator>::Item, T, O>,
+    caller: leak::Caller,
 }
 
-impl<'a, I> Iterator for Tickets<'a, I> where I: Iterator, <I as Iterator>::Item: 'a {
-    type Item = Ticket;
+impl<'a, I, T, O> Iterator for Tickets<'a, I, T, O> where
+    I: Iterator,
+    <I as Iterator>::Item: 'a,
+    T: TicketType + 'a,
+    O: Observer<<I as Iterator>::Item> + 'a
+{
+    type Item = T;
 
-    fn next(&mut self) -> Option<Ticket> {
-        self.iter.next().map(|v| self.cc.check(v))
+    fn next(&mut self) -> Option<T> {
+        let caller = self.caller;
+        self.iter.next().map(|v| self.cc.check_at(v, caller))
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.iter.size_hint()
     }
 }
 
-impl<'a, I> ExactSizeIterator for Tickets<'a, I> where
+impl<'a, I, T, O> ExactSizeIterator for Tickets<'a, I, T, O> where
     I: ExactSizeIterator,
-    <I as Iterator>::Item: 'a
+    <I as Iterator>::Item: 'a,
+    T: TicketType + 'a,
+    O: Observer<<I as Iterator>::Item> + 'a
 { }
 
-impl<'a, I> DoubleEndedIterator for Tickets<'a, I> where
+impl<'a, I, T, O> DoubleEndedIterator for Tickets<'a, I, T, O> where
     I: DoubleEndedIterator,
-    <I as Iterator>::Item: 'a
+    <I as Iterator>::Item: 'a,
+    T: TicketType + 'a,
+    O: Observer<<I as Iterator>::Item> + 'a
 {
-    fn next_back(&mut self) -> Option<Ticket> {
-        self.iter.next_back().map(|v| self.cc.check(v))
+    fn next_back(&mut self) -> Option<T> {
+        let caller = self.caller;
+        self.iter.next_back().map(|v| self.cc.check_at(v, caller))
     }
 }
 
-#[doc(hidden)]
-struct GenericIter<I> where I: Iterator {
-    inner: I,
-    remaining: usize,
+/// Slots along with their indices.
+type WithIds<S> = iter::Enumerate<S>;
 
+/// A source of slots for the coat check iterators.
+///
+/// `C` is the context needed to turn a full slot into an item (the coat check's tag and epoch for
+/// the `*WithIds` iterators).
+trait Slots<C>: DoubleEndedIterator {
+    type Value;
+    /// Get the item for a slot (`None` if the slot isn't full).
+    fn value(item: Self::Item, context: &C) -> Option<Self::Value>;
 }
 
-impl<I> ExactSizeIterator for GenericIter<I> where I: Iterator {
+impl<'a, V> Slots<()> for slice::Iter<'a, Entry<V>> {
+    type Value = &'a V;
     #[inline]
-    fn len(&self) -> usize {
-        self.remaining
+    fn value(entry: &'a Entry<V>, _: &()) -> Option<&'a V> {
+        entry.full_ref()
     }
 }
 
-impl<I> Iterator for GenericIter<I> where I: Iterator {
-    type Item = <I as Iterator>::Item;
-    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        if self.remaining > 0 {
-            self.remaining -= 1;
-            self.inner.next()
-        } else {
-            None
+impl<'a, V> Slots<()> for slice::IterMut<'a, Entry<V>> {
+    type Value = &'a mut V;
+    #[inline]
+    fn value(entry: &'a mut Entry<V>, _: &()) -> Option<&'a mut V> {
+        entry.full_mut()
+    }
+}
+
+impl<V> Slots<()> for vec::IntoIter<Entry<V>> {
+    type Value = V;
+    #[inline]
+    fn value(entry: Entry<V>, _: &()) -> Option<V> {
+        entry.full()
+    }
+}
+
+impl<'a, V> Slots<()> for vec::Drain<'a, Entry<V>> {
+    type Value = V;
+    #[inline]
+    fn value(entry: Entry<V>, _: &()) -> Option<V> {
+        entry.full()
+    }
+}
+
+impl<'a, V> Slots<(ProcessUniqueId, u64)> for WithIds<slice::Iter<'a, Entry<V>>> {
+    type Value = (TicketId, &'a V);
+    #[inline]
+    fn value((index, entry): Self::Item, &(tag, epoch): &(ProcessUniqueId, u64)) -> Option<Self::Value> {
+        entry.full_ref().map(|v| (TicketId { tag: tag, epoch: epoch, index: index }, v))
+    }
+}
+
+impl<'a, V> Slots<(ProcessUniqueId, u64)> for WithIds<slice::IterMut<'a, Entry<V>>> {
+    type Value = (TicketId, &'a mut V);
+    #[inline]
+    fn value((index, entry): Self::Item, &(tag, epoch): &(ProcessUniqueId, u64)) -> Option<Self::Value> {
+        entry.full_mut().map(|v| (TicketId { tag: tag, epoch: epoch, index: index }, v))
+    }
+}
+
+impl<V> Slots<(ProcessUniqueId, u64)> for WithIds<vec::IntoIter<Entry<V>>> {
+    type Value = (TicketId, V);
+    #[inline]
+    fn value((index, entry): Self::Item, &(tag, epoch): &(ProcessUniqueId, u64)) -> Option<Self::Value> {
+        entry.full().map(|v| (TicketId { tag: tag, epoch: epoch, index: index }, v))
+    }
+}
+
+/// Walks the slots, skipping those that aren't full, until it has yielded `remaining` values.
+#[derive(Clone)]
+struct Walk<I, C = ()> {
+    slots: I,
+    context: C,
+    remaining: usize,
+}
+
+impl<I, C> Walk<I, C> {
+    #[inline]
+    fn new(slots: I, context: C, remaining: usize) -> Self {
+        Walk { slots: slots, context: context, remaining: remaining }
+    }
+}
+
+impl<I, C> Iterator for Walk<I, C> where I: Slots<C> {
+    type Item = <I as Slots<C>>::Value;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining > 0 {
+            match self.slots.next() {
+                Some(slot) => if let Some(value) = I::value(slot, &self.context) {
+                    self.remaining -= 1;
+                    return Some(value);
+                },
+                None => break,
+            }
         }
+        None
     }
+
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len(), Some(self.len()))
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<I, C> DoubleEndedIterator for Walk<I, C> where I: Slots<C> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.remaining > 0 {
+            match self.slots.next_back() {
+                Some(slot) => if let Some(value) = I::value(slot, &self.context) {
+                    self.remaining -= 1;
+                    return Some(value);
+                },
+                None => break,
+            }
+        }
+        None
+    }
+}
+
+/// Implement the iterator traits for a wrapper around a `Walk`.
+macro_rules! walk_iterator {
+    ($name:ident<$($param:tt),*> => $item:ty) => {
+        impl<$($param),*> Iterator for $name<$($param),*> {
+            type Item = $item;
+            #[inline]
+            fn next(&mut self) -> Option<$item> {
+                self.walk.next()
+            }
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.walk.size_hint()
+            }
+        }
+
+        impl<$($param),*> DoubleEndedIterator for $name<$($param),*> {
+            #[inline]
+            fn next_back(&mut self) -> Option<$item> {
+                self.walk.next_back()
+            }
+        }
+
+        impl<$($param),*> ExactSizeIterator for $name<$($param),*> {}
+        impl<$($param),*> FusedIterator for $name<$($param),*> {}
+    };
+}
+
+/// An iterator over the values in a `CoatCheck<V>` (see `CoatCheck::iter`).
+pub struct Iter<'a, V: 'a> {
+    walk: Walk<slice::Iter<'a, Entry<V>>>,
+}
+
+walk_iterator!(Iter<'a, V> => &'a V);
+
+impl<'a, V> Clone for Iter<'a, V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Iter { walk: self.walk.clone() }
+    }
+}
+
+/// A mutable iterator over the values in a `CoatCheck<V>` (see `CoatCheck::iter_mut`).
+pub struct IterMut<'a, V: 'a> {
+    walk: Walk<slice::IterMut<'a, Entry<V>>>,
+}
+
+walk_iterator!(IterMut<'a, V> => &'a mut V);
+
+/// A consuming iterator over the values in a `CoatCheck<V>`.
+pub struct IntoIter<V> {
+    walk: Walk<vec::IntoIter<Entry<V>>>,
+}
+
+walk_iterator!(IntoIter<V> => V);
+
+impl<V> Clone for IntoIter<V> where V: Clone {
+    #[inline]
+    fn clone(&self) -> Self {
+        IntoIter { walk: self.walk.clone() }
+    }
+}
+
+/// A draining iterator over the values in a `CoatCheck<V>` (see `CoatCheck::drain`).
+pub struct Drain<'a, V: 'a> {
+    walk: Walk<vec::Drain<'a, Entry<V>>>,
+}
+
+walk_iterator!(Drain<'a, V> => V);
+
+/// An iterator over the values in a `CoatCheck<V>` and their ticket ids (see
+/// `CoatCheck::iter_with_ids`).
+pub struct IterWithIds<'a, V: 'a> {
+    walk: Walk<WithIds<slice::Iter<'a, Entry<V>>>, (ProcessUniqueId, u64)>,
+}
+
+walk_iterator!(IterWithIds<'a, V> => (TicketId, &'a V));
+
+impl<'a, V> Clone for IterWithIds<'a, V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        IterWithIds { walk: self.walk.clone() }
+    }
+}
+
+/// A mutable iterator over the values in a `CoatCheck<V>` and their ticket ids (see
+/// `CoatCheck::iter_mut_with_ids`).
+pub struct IterMutWithIds<'a, V: 'a> {
+    walk: Walk<WithIds<slice::IterMut<'a, Entry<V>>>, (ProcessUniqueId, u64)>,
+}
+
+walk_iterator!(IterMutWithIds<'a, V> => (TicketId, &'a mut V));
+
+/// A consuming iterator over the values in a `CoatCheck<V>` and their ticket ids (see
+/// `CoatCheck::into_iter_with_ids`).
+pub struct IntoIterWithIds<V> {
+    walk: Walk<WithIds<vec::IntoIter<Entry<V>>>, (ProcessUniqueId, u64)>,
+}
+
+walk_iterator!(IntoIterWithIds<V> => (TicketId, V));
+
+impl<V> Clone for IntoIterWithIds<V> where V: Clone {
+    #[inline]
+    fn clone(&self) -> Self {
+        IntoIterWithIds { walk: self.walk.clone() }
+    }
+}
+
+/// A value lent out of a coat check (see `CoatCheck::lend`).
+///
+/// Dereferences to the value. Put it back with `CoatCheck::restore`.
+#[must_use = "the value's slot stays reserved until the loan is restored"]
+pub struct Loan<V> {
+    tag: ProcessUniqueId,
+    epoch: u64,
+    index: usize,
+    value: V,
+}
+
+impl<V> Deref for Loan<V> {
+    type Target = V;
+    #[inline]
+    fn deref(&self) -> &V {
+        &self.value
     }
 }
 
-#[doc(hidden)]
-pub type IntoIter<V> = GenericIter<iter::FilterMap<
-    vec::IntoIter<Entry<V>>, fn(Entry<V>) -> Option<V>
->>;
+impl<V> DerefMut for Loan<V> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+}
 
-#[doc(hidden)]
-pub type Iter<'a, V> = GenericIter< iter::FilterMap<
-    slice::Iter<'a, Entry<V>>, fn(&'a Entry<V>) -> Option<&'a V>
->>;
+impl<V> fmt::Debug for Loan<V> where V: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Loan({:?})", self.value)
+    }
+}
 
-#[doc(hidden)]
-pub type IterMut<'a, V> = GenericIter<iter::FilterMap<
-    slice::IterMut<'a, Entry<V>>,
-    fn(&'a mut Entry<V>) -> Option<&'a mut V>
->>;
+/// A slot reserved by `CoatCheck::reserve_slot`, waiting for its value.
+///
+/// Like tickets, deposits can't be copied, cloned, or forged.
+#[must_use = "the slot stays reserved until the deposit is filled"]
+pub struct Deposit {
+    tag: ProcessUniqueId,
+    epoch: u64,
+    index: usize,
+}
+
+impl Deposit {
+    /// Get the id of the ticket issued along with this deposit.
+    #[inline]
+    pub fn id(&self) -> TicketId {
+        TicketId { tag: self.tag, epoch: self.epoch, index: self.index }
+    }
+
+    /// Fill the reserved slot, making the value available to the ticket holder.
+    ///
+    /// Returns `Err(ClaimError)` if the deposit belongs to another `CoatCheck<V>` or was made
+    /// before the coat check was last cleared (returning the deposit and the value inside of the
+    /// ClaimError).
+    pub fn fill<V, T, O>(self, cc: &mut CoatCheck<V, T, O>, value: V) -> Result<(), ClaimError<(Deposit, V)>> where
+        T: TicketType,
+        O: Observer<V>
+    {
+        if self.tag != cc.tag {
+            return Err(ClaimError { ticket: (self, value), kind: ErrorKind::WrongCoatCheck });
+        }
+        if self.epoch != cc.epoch {
+            return Err(ClaimError { ticket: (self, value), kind: ErrorKind::Expired });
+        }
+        cc.observer.on_check(&self.id(), &value);
+        // Safe because deposits can't be forged or duplicated so the slot must exist (and be
+        // reserved).
+        let entry = unsafe { cc.data.get_unchecked_mut(self.index) };
+        debug_assert!(entry.is_reserved());
+        *entry = Full(value);
+        cc.pending -= 1;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Deposit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Deposit")
+    }
+}
 
 /// A data structure storing values indexed by tickets.
-pub struct CoatCheck<V> {
+///
+/// The second type parameter is the type of the tickets it hands out (see `TicketType`) and the
+/// third is the `Observer` notified of checks and claims.
+pub struct CoatCheck<V, T = Ticket, O = ()> {
     tag: ProcessUniqueId,
     data: Vec<Entry<V>>,
     size: usize,
     next_free: usize,
+    epoch: u64,
+    /// The number of values on loan.
+    lent: usize,
+    /// The number of reserved slots waiting for a deposit.
+    pending: usize,
+    /// The number of slots left behind by values moved to another coat check.
+    moved: usize,
+    locations: leak::Locations,
+    observer: O,
+    _ticket: PhantomData<fn(T) -> T>,
 }
 
 impl<V> CoatCheck<V> {
@@ -446,14 +1060,112 @@ impl<V> CoatCheck<V> {
     /// ```
     #[inline]
     pub fn with_capacity(capacity: usize) -> Self {
+        CoatCheck::typed_with_capacity(capacity)
+    }
+}
+
+impl<V, T> CoatCheck<V, T> where T: TicketType {
+    /// Constructs a new, empty `CoatCheck<V, T>` handing out tickets of type `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[macro_use]
+    /// extern crate coatcheck;
+    ///
+    /// use coatcheck::CoatCheck;
+    ///
+    /// define_ticket!(struct Handle;);
+    ///
+    /// # fn main() {
+    /// let mut cc: CoatCheck<i32, Handle> = CoatCheck::typed();
+    /// let handle: Handle = cc.check(1);
+    /// assert_eq!(cc[&handle], 1);
+    /// # }
+    /// ```
+    #[inline]
+    pub fn typed() -> Self {
+        CoatCheck::typed_with_capacity(0)
+    }
+
+    /// Constructs a new, empty `CoatCheck<V, T>` handing out tickets of type `T` with the
+    /// specified capacity.
+    ///
+    /// See `CoatCheck::with_capacity` for details.
+    #[inline]
+    pub fn typed_with_capacity(capacity: usize) -> Self {
+        CoatCheck::from_parts(capacity, ())
+    }
+}
+
+impl<V, O> CoatCheck<V, Ticket, O> where O: Observer<V> {
+    /// Constructs a new, empty `CoatCheck<V>` that reports checks, claims, and rejected tickets to
+    /// `observer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::cell::Cell;
+    /// use coatcheck::{CoatCheck, Observer, TicketId};
+    ///
+    /// struct Counter(Cell<usize>);
+    ///
+    /// impl<V> Observer<V> for Counter {
+    ///     fn on_check(&self, _: &TicketId, _: &V) {
+    ///         self.0.set(self.0.get() + 1);
+    ///     }
+    /// }
+    ///
+    /// let mut cc = CoatCheck::with_observer(Counter(Cell::new(0)));
+    /// let _ticket = cc.check("value");
+    /// assert_eq!(cc.observer().0.get(), 1);
+    /// ```
+    #[inline]
+    pub fn with_observer(observer: O) -> Self {
+        CoatCheck::from_parts(0, observer)
+    }
+}
+
+impl<V, T, O> CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
+    /// Constructs a new, empty `CoatCheck<V, T>` handing out tickets of type `T` that reports to
+    /// `observer`.
+    ///
+    /// See `CoatCheck::typed` and `CoatCheck::with_observer` for details.
+    #[inline]
+    pub fn typed_with_observer(observer: O) -> Self {
+        CoatCheck::from_parts(0, observer)
+    }
+
+    fn from_parts(capacity: usize, observer: O) -> Self {
+        let tag = ProcessUniqueId::new();
+        leak::register(tag);
         CoatCheck {
-            tag: ProcessUniqueId::new(),
+            tag: tag,
             data: Vec::with_capacity(capacity),
             next_free: 0,
             size: 0,
+            epoch: 0,
+            lent: 0,
+            pending: 0,
+            moved: 0,
+            locations: Default::default(),
+            observer: observer,
+            _ticket: PhantomData,
         }
     }
 
+    /// Get a reference to this coat check's observer.
+    #[inline]
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Get a mutable reference to this coat check's observer.
+    #[inline]
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
     /// Returns the number of elements the coat check can hold without reallocating.
     ///
     /// # Examples
@@ -504,7 +1216,7 @@ impl<V> CoatCheck<V> {
     /// assert!(cc.capacity() >= 11);
     /// ```
     pub fn reserve(&mut self, additional: usize) {
-        let extra_space = self.data.len() - self.len();
+        let extra_space = self.data.len() - self.len() - self.moved;
         if extra_space < additional {
             self.data.reserve(additional - extra_space)
         }
@@ -532,7 +1244,7 @@ impl<V> CoatCheck<V> {
     /// assert!(cc.capacity() >= 11);
     /// ```
     pub fn reserve_exact(&mut self, additional: usize){
-        let extra_space = self.data.len() - self.len();
+        let extra_space = self.data.len() - self.len() - self.moved;
         if extra_space < additional {
             self.data.reserve_exact(additional - extra_space)
         }
@@ -544,19 +1256,123 @@ impl<V> CoatCheck<V> {
     /// claim values from this CoatCheck.
     ///
     /// *Panics* if the size of the `CoatCheck<V>` would overflow `usize::MAX`.
-    pub fn check(&mut self, value: V) -> Ticket {
+    #[inline]
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn check(&mut self, value: V) -> T {
+        self.check_at(value, leak::caller())
+    }
+
+    /// Build a value from its own ticket id, check it in, and get a `Ticket` in exchange.
+    ///
+    /// The slot (and id) are reserved before `f` is called. If `f` panics, nothing is checked in
+    /// and the slot is left free (its id goes to the next value checked in). `f` can't use this
+    /// `CoatCheck<V>` (it's mutably borrowed until `check_with` returns).
+    ///
+    /// *Panics* if the size of the `CoatCheck<V>` would overflow `usize::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::{CoatCheck, TicketId};
+    ///
+    /// struct Job { id: TicketId }
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let ticket = cc.check_with(|id| Job { id: id });
+    /// assert!(cc[&ticket].id == ticket.id());
+    /// # cc.claim(ticket).unwrap();
+    /// ```
+    ///
+    /// Re-entrant use is rejected at compile time:
+    ///
+    /// ```compile_fail
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let ticket = cc.check_with(|_| cc.check(1));
+    /// ```
+    #[inline]
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn check_with<F>(&mut self, f: F) -> T where F: FnOnce(TicketId) -> V {
+        let caller = leak::caller();
+        let id = self.reserve_id();
+        let value = f(id);
+        self.check_as(id, value, caller)
+    }
+
+    /// Check a value in, recording the caller (when leak checking).
+    #[inline]
+    fn check_at(&mut self, value: V, caller: leak::Caller) -> T {
+        let id = self.reserve_id();
+        self.check_as(id, value, caller)
+    }
+
+    /// Pick the id for the next value checked in.
+    ///
+    /// Until the value is checked in (`check_as`), the slot stays on the free list so there's
+    /// nothing to undo if the value is never checked in.
+    #[inline]
+    fn reserve_id(&mut self) -> TicketId {
+        TicketId { tag: self.tag, epoch: self.epoch, index: self.next_free }
+    }
+
+    /// Check a value in under an id from `reserve_id` (without touching the free list in
+    /// between).
+    fn check_as(&mut self, id: TicketId, value: V, caller: leak::Caller) -> T {
+        self.observer.on_check(&id, &value);
+        self.occupy(id, Full(value), caller)
+    }
+
+    /// Put an entry into the slot reserved by `reserve_id`.
+    fn occupy(&mut self, id: TicketId, entry: Entry<V>, caller: leak::Caller) -> T {
         let loc = self.next_free;
         debug_assert!(loc <= self.data.len());
+        debug_assert!(id.index == loc);
 
         self.next_free = if self.next_free == self.data.len() {
-            self.data.push(Full(value));
+            self.data.push(entry);
             self.next_free.checked_add(1).unwrap()
         } else {
             // Safe because we've recorded that it is safe.
-            unsafe { self.data.get_unchecked_mut(loc) }.fill(value)
+            unsafe { self.data.get_unchecked_mut(loc) }.fill(entry)
         };
         self.size += 1;
-        Ticket { tag: self.tag, index: loc }
+        self.locations.record(loc, caller);
+        T::from_ticket(Ticket { tag: self.tag, epoch: id.epoch, index: loc })
+    }
+
+    /// Reserve a slot, getting a `Ticket` for a value that will be deposited later.
+    ///
+    /// Until the value is deposited (`Deposit::fill`), using the ticket fails with
+    /// `ErrorKind::Pending` (claiming it hands the ticket back).
+    ///
+    /// *Note:* Reserved slots count towards `len` but are skipped when iterating. If you drop a
+    /// deposit instead of filling it, its slot stays reserved until the coat check is cleared.
+    ///
+    /// *Panics* if the size of the `CoatCheck<V>` would overflow `usize::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::{CoatCheck, ErrorKind};
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let (ticket, deposit) = cc.reserve_slot();
+    /// assert!(match cc.get(&ticket).unwrap_err().kind {
+    ///     ErrorKind::Pending => true,
+    ///     _ => false,
+    /// });
+    /// let ticket = cc.claim(ticket).unwrap_err().into_ticket();
+    ///
+    /// deposit.fill(&mut cc, "response").unwrap();
+    /// assert_eq!(cc.claim(ticket).unwrap(), "response");
+    /// ```
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn reserve_slot(&mut self) -> (T, Deposit) {
+        let id = self.reserve_id();
+        let ticket = self.occupy(id, Reserved, leak::caller());
+        self.pending += 1;
+        (ticket, Deposit { tag: id.tag, epoch: id.epoch, index: id.index })
     }
 
     /// Check all the items in an iterator and get tickets back.
@@ -564,39 +1380,131 @@ impl<V> CoatCheck<V> {
     /// *Warning:* If you don't take your tickets (collect them from the iterator), you're items
     /// won't be checked.
     #[inline]
-    pub fn check_all<I>(&mut self, iter: I) -> Tickets<I> where I: Iterator<Item=V> {
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn check_all<I>(&mut self, iter: I) -> Tickets<'_, I, T, O> where I: Iterator<Item=V> {
         let (lower, _) = iter.size_hint();
         self.reserve(lower);
-        Tickets { iter: iter, cc: self }
+        Tickets { iter: iter, cc: self, caller: leak::caller() }
     }
 
     /// Iterate over the items in this `CoatCheck<V>`.
     #[inline]
     pub fn iter<'a>(&'a self) -> Iter<'a, V> {
-        Iter {
-            remaining: self.len(),
-            inner: self.data.iter().filter_map(Entry::<V>::full_ref as fn(&'a Entry<V>) -> Option<&'a V>),
-        }
+        Iter { walk: Walk::new(self.data.iter(), (), self.available()) }
     }
 
     /// Mutably iterate over the items in this `CoatCheck<V>`.
     #[inline]
     pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, V> {
-        IterMut {
-            remaining: self.len(),
-            inner: self.data.iter_mut().filter_map(Entry::<V>::full_mut as fn(&'a mut Entry<V>) -> Option<&'a mut V>)
-        }
+        let remaining = self.available();
+        IterMut { walk: Walk::new(self.data.iter_mut(), (), remaining) }
+    }
+
+    /// Iterate over the items in this `CoatCheck<V>` along with the ids of their tickets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let a = cc.check("a");
+    /// let b = cc.check("b");
+    ///
+    /// let ids: Vec<_> = cc.iter_with_ids().map(|(id, _)| id).collect();
+    /// assert_eq!(ids, vec![a.id(), b.id()]);
+    /// # cc.claim(a).unwrap();
+    /// # cc.claim(b).unwrap();
+    /// ```
+    #[inline]
+    pub fn iter_with_ids<'a>(&'a self) -> IterWithIds<'a, V> {
+        let slots = self.data.iter().enumerate();
+        IterWithIds { walk: Walk::new(slots, (self.tag, self.epoch), self.available()) }
+    }
+
+    /// Mutably iterate over the items in this `CoatCheck<V>` along with the ids of their tickets.
+    #[inline]
+    pub fn iter_mut_with_ids<'a>(&'a mut self) -> IterMutWithIds<'a, V> {
+        let remaining = self.available();
+        let slots = self.data.iter_mut().enumerate();
+        IterMutWithIds { walk: Walk::new(slots, (self.tag, self.epoch), remaining) }
+    }
+
+    /// Consume this `CoatCheck<V>`, iterating over its items along with the ids of their tickets.
+    ///
+    /// See `CoatCheck::into_iter` for details.
+    pub fn into_iter_with_ids(mut self) -> IntoIterWithIds<V> {
+        let remaining = self.available();
+        // The values are moved out (so they aren't reported as still checked in on drop).
+        self.size = 0;
+        let data = mem::replace(&mut self.data, Vec::new());
+        let slots = data.into_iter().enumerate();
+        IntoIterWithIds { walk: Walk::new(slots, (self.tag, self.epoch), remaining) }
     }
 
     /// Check if a ticket belongs to this `CoatCheck<V>`.
     ///
     /// Returns true if the ticket belongs to this `CoatCheck<V>`.
     #[inline]
-    pub fn contains_ticket(&self, ticket: &Ticket) -> bool {
+    pub fn contains_ticket(&self, ticket: &T) -> bool {
         // Tickets can't be forged or duplicated so a matching tag SHOULD mean that the ticket is
         // valid.
-        debug_assert!(self.data[ticket.index].is_full());
-        ticket.tag == self.tag
+        let ticket = ticket.as_ticket();
+        if ticket.tag == self.tag && ticket.epoch == self.epoch {
+            debug_assert!(match self.data[ticket.index] { Empty(_) => false, _ => true });
+            // The value may have been moved to another coat check (see `split_off`).
+            !self.data[ticket.index].is_moved()
+        } else {
+            false
+        }
+    }
+
+    /// Remove the value at `index`, freeing the slot.
+    ///
+    /// Unsafe because the slot must exist (it must be full).
+    #[inline]
+    unsafe fn remove(&mut self, index: usize) -> V {
+        let value = self.data.get_unchecked_mut(index).empty(self.next_free);
+        self.next_free = index;
+        self.size -= 1;
+        self.observer.on_claim(&TicketId { tag: self.tag, epoch: self.epoch, index: index }, &value);
+        value
+    }
+
+    /// Check that a ticket was issued by this coat check (in the current epoch), returning its
+    /// index.
+    ///
+    /// Reports rejected tickets to the observer.
+    #[inline]
+    fn validate(&self, ticket: &Ticket) -> Result<usize, ErrorKind> {
+        if ticket.tag != self.tag {
+            Err(self.reject(ErrorKind::WrongCoatCheck))
+        } else if ticket.epoch != self.epoch {
+            Err(self.reject(ErrorKind::Expired))
+        } else if self.lent == 0 && self.pending == 0 && self.moved == 0 {
+            Ok(ticket.index)
+        } else {
+            // Safe because, if we've handed out the ticket, this slot must exist.
+            match unsafe { self.data.get_unchecked(ticket.index) } {
+                &Lent => Err(self.reject(ErrorKind::OnLoan)),
+                &Reserved => Err(self.reject(ErrorKind::Pending)),
+                &Moved => Err(self.reject(ErrorKind::Moved)),
+                _ => Ok(ticket.index),
+            }
+        }
+    }
+
+    /// The number of values that are actually here (neither lent nor pending).
+    #[inline]
+    fn available(&self) -> usize {
+        self.size - self.lent - self.pending
+    }
+
+    /// Report an access error to the observer.
+    #[inline]
+    fn reject(&self, kind: ErrorKind) -> ErrorKind {
+        self.observer.on_access_error(kind);
+        kind
     }
 
     /// Check if this `CoatCheck<V>` is empty.
@@ -610,101 +1518,411 @@ impl<V> CoatCheck<V> {
     /// Claim an item.
     ///
     /// Returns `Ok(value)` if the ticket belongs to this `CoatCheck<V>` (eating the ticket).
-    /// Returns `Err(ClaimError)` if the ticket belongs to another `CoatCheck<V>` (returning the
-    /// ticket inside of the ClaimError).
-    pub fn claim(&mut self, ticket: Ticket) -> Result<V, ClaimError> {
-        match ticket {
-            Ticket { tag, index } if tag == self.tag => {
+    /// Returns `Err(ClaimError)` if the ticket belongs to another `CoatCheck<V>`, was issued
+    /// before the coat check was last cleared, or if the value is on loan (returning the ticket
+    /// inside of the ClaimError).
+    pub fn claim(&mut self, ticket: T) -> Result<V, ClaimError<T>> {
+        match self.validate(ticket.as_ticket()) {
+            Ok(index) => {
                 // Safe because, if we've handed out the ticket, this slot must exist.
-                let value = unsafe { self.data.get_unchecked_mut(index) }.empty(self.next_free);
-                self.next_free = index;
-                self.size -= 1;
+                let value = unsafe { self.remove(index) };
+                ticket.into_ticket().consume();
                 Ok(value)
             },
-            _ => Err(ClaimError { ticket: ticket, kind: ErrorKind::WrongCoatCheck })
+            Err(kind) => Err(ClaimError { ticket: ticket, kind: kind })
+        }
+    }
+
+    /// Move a value into another coat check.
+    ///
+    /// Returns `Ok(ticket)` (a ticket for `dest`) if the ticket belongs to this `CoatCheck<V>`
+    /// (eating the ticket).
+    /// Returns `Err(ClaimError)` if the ticket belongs to another `CoatCheck<V>` (returning the
+    /// ticket, untouched, inside of the ClaimError).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut a = CoatCheck::new();
+    /// let mut b = CoatCheck::new();
+    /// let ticket = a.check("value");
+    /// let ticket = a.transfer(ticket, &mut b).unwrap();
+    /// assert!(a.is_empty());
+    /// assert_eq!(b.claim(ticket).unwrap(), "value");
+    /// ```
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn transfer<P>(&mut self, ticket: T, dest: &mut CoatCheck<V, T, P>) -> Result<T, ClaimError<T>> where
+        P: Observer<V>
+    {
+        let value = self.claim(ticket)?;
+        Ok(dest.check_at(value, leak::caller()))
+    }
+
+    /// Turn a `RawTicket` back into a ticket.
+    ///
+    /// Returns `Ok(ticket)` if the raw ticket's slot holds a value (or a value on loan or pending,
+    /// see `CoatCheck::lend` and `CoatCheck::reserve_slot`).
+    /// Returns `Err(ClaimError)` with `ErrorKind::Expired` if the raw ticket was issued before the
+    /// coat check was last cleared and `ErrorKind::InvalidTicket` if its slot is empty (returning
+    /// the raw ticket inside of the ClaimError).
+    ///
+    /// *Warning:* Raw tickets only record the slot, not the value or the coat check. This can't
+    /// tell whether or not a raw ticket has already been redeemed, whether its slot has been
+    /// reused, or whether it was issued by another coat check. In all of these cases, the ticket
+    /// refers to whatever value is now in the slot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::{CoatCheck, RawTicket, Ticket};
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let (epoch, index) = cc.check("value").into_raw().into_parts();
+    ///
+    /// // ...pass `epoch` and `index` through some C code...
+    ///
+    /// let raw = RawTicket::from_parts(epoch, index);
+    /// let ticket: Ticket = cc.redeem_raw(raw).unwrap();
+    /// assert_eq!(cc.claim(ticket).unwrap(), "value");
+    /// assert!(cc.redeem_raw(raw).is_err());
+    /// ```
+    pub fn redeem_raw(&self, raw: RawTicket) -> Result<T, ClaimError<RawTicket>> {
+        if raw.epoch != self.epoch {
+            return Err(ClaimError { ticket: raw, kind: ErrorKind::Expired });
+        }
+        match self.data.get(raw.index) {
+            // Safe because the slot is occupied.
+            Some(&Full(_)) | Some(&Lent) | Some(&Reserved) => Ok(unsafe { self.redeem_raw_unchecked(raw) }),
+            _ => Err(ClaimError { ticket: raw, kind: ErrorKind::InvalidTicket }),
         }
     }
 
+    /// Turn a `RawTicket` back into a ticket without checking it.
+    ///
+    /// Prefer `CoatCheck::redeem_raw`.
+    ///
+    /// # Safety
+    ///
+    /// Using a ticket trusts its index, so the caller must ensure that:
+    ///
+    /// * the raw ticket came from `Ticket::into_raw` on a ticket issued by this coat check,
+    /// * this coat check hasn't been cleared (or drained) since the ticket was issued,
+    /// * the ticket's slot still holds the value the ticket was issued for, and
+    /// * no other live ticket refers to the slot (i.e., the raw ticket is converted back at most
+    ///   once and the original ticket was consumed by `into_raw`).
+    #[inline]
+    pub unsafe fn redeem_raw_unchecked(&self, raw: RawTicket) -> T {
+        T::from_ticket(Ticket { tag: self.tag, epoch: raw.epoch, index: raw.index })
+    }
+
     /// Get a reference to the value matching this ticket.
     ///
     /// Returns `Ok(&value)` if the ticket belongs to this `CoatCheck<V>`.
-    /// Returns `Err(AccessError)` if the ticket belongs to another `CoatCheck<V>`.
-    pub fn get(&self, ticket: &Ticket) -> Result<&V, AccessError> {
-        match ticket {
-            &Ticket { tag, index } if tag == self.tag => match unsafe {
+    /// Returns `Err(AccessError)` if the ticket belongs to another `CoatCheck<V>` or was issued
+    /// before the coat check was last cleared.
+    pub fn get(&self, ticket: &T) -> Result<&V, AccessError> {
+        match self.validate(ticket.as_ticket()) {
+            Ok(index) => match unsafe {
                 // Safe because, if we've handed out the ticket, this slot must exist.
                 self.data.get_unchecked(index)
             } {
                 &Full(ref v) => Ok(v),
                 _ => panic!("forged ticket"),
             },
-            _ =>  Err(AccessError { kind: ErrorKind::WrongCoatCheck })
+            Err(kind) =>  Err(AccessError { kind: kind })
         }
     }
 
     /// Get a mutable reference to the value matching this ticket.
     ///
     /// Returns `Ok(&mut value)` if the ticket belongs to this `CoatCheck<V>`.
-    /// Returns `Err(AccessError)` if the ticket belongs to another `CoatCheck<V>`.
-    pub fn get_mut(&mut self, ticket: &Ticket) -> Result<&mut V, AccessError> {
-        match ticket {
-            &Ticket { tag, index } if tag == self.tag => match unsafe {
+    /// Returns `Err(AccessError)` if the ticket belongs to another `CoatCheck<V>` or was issued
+    /// before the coat check was last cleared.
+    pub fn get_mut(&mut self, ticket: &T) -> Result<&mut V, AccessError> {
+        match self.validate(ticket.as_ticket()) {
+            Ok(index) => match unsafe {
                 // Safe because, if we've handed out the ticket, this slot must exist.
                 self.data.get_unchecked_mut(index)
             } {
                 &mut Full(ref mut v) => Ok(v),
                 _ => panic!("forged ticket"),
             },
-            _ =>  Err(AccessError { kind: ErrorKind::WrongCoatCheck })
+            Err(kind) =>  Err(AccessError { kind: kind })
+        }
+    }
+
+    /// Temporarily move a value out of this `CoatCheck<V>`.
+    ///
+    /// The value's slot stays reserved until the value is put back with `restore`. Until then,
+    /// using the ticket fails with `ErrorKind::OnLoan`. This lets you call a value (e.g., a
+    /// callback) that needs mutable access to whatever owns the coat check.
+    ///
+    /// Returns `Ok(loan)` if the ticket belongs to this `CoatCheck<V>`.
+    /// Returns `Err(AccessError)` if the ticket belongs to another `CoatCheck<V>`, was issued
+    /// before the coat check was last cleared, or if the value is already on loan.
+    ///
+    /// *Note:* Lent values still count towards `len` but are skipped when iterating. If you drop a
+    /// loan instead of restoring it, its slot stays reserved until the coat check is cleared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::{CoatCheck, ErrorKind};
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let ticket = cc.check(vec![1]);
+    ///
+    /// let mut loan = cc.lend(&ticket).unwrap();
+    /// loan.push(2);
+    /// assert!(match cc.get(&ticket).unwrap_err().kind {
+    ///     ErrorKind::OnLoan => true,
+    ///     _ => false,
+    /// });
+    ///
+    /// cc.restore(loan).unwrap();
+    /// assert_eq!(cc.claim(ticket).unwrap(), vec![1, 2]);
+    /// ```
+    pub fn lend(&mut self, ticket: &T) -> Result<Loan<V>, AccessError> {
+        let index = self.validate(ticket.as_ticket()).map_err(|kind| AccessError { kind: kind })?;
+        // Safe because, if we've handed out the ticket, this slot must exist.
+        let value = match mem::replace(unsafe { self.data.get_unchecked_mut(index) }, Lent) {
+            Full(value) => value,
+            _ => panic!("forged ticket"),
+        };
+        self.lent += 1;
+        Ok(Loan { tag: self.tag, epoch: self.epoch, index: index, value: value })
+    }
+
+    /// Put a lent value back.
+    ///
+    /// Returns `Err(ClaimError)` if the loan belongs to another `CoatCheck<V>` or was made before
+    /// the coat check was last cleared (returning the loan inside of the ClaimError).
+    pub fn restore(&mut self, loan: Loan<V>) -> Result<(), ClaimError<Loan<V>>> {
+        if loan.tag != self.tag {
+            return Err(ClaimError { ticket: loan, kind: ErrorKind::WrongCoatCheck });
+        }
+        if loan.epoch != self.epoch {
+            return Err(ClaimError { ticket: loan, kind: ErrorKind::Expired });
         }
+        // Safe because loans can't be forged or duplicated so the slot must exist (and be lent).
+        let entry = unsafe { self.data.get_unchecked_mut(loan.index) };
+        debug_assert!(entry.is_lent());
+        *entry = Full(loan.value);
+        self.lent -= 1;
+        Ok(())
+    }
+
+    /// Get mutable references to the values matching several tickets at once.
+    ///
+    /// Returns `Ok([&mut value; N])` if all tickets belong to this `CoatCheck<V>`.
+    /// Returns `Err(AccessError)` if any ticket belongs to another `CoatCheck<V>` (or was issued
+    /// before the coat check was last cleared) or if the same ticket was passed more than once
+    /// (`ErrorKind::DuplicateTicket`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let a = cc.check(1);
+    /// let b = cc.check(2);
+    /// {
+    ///     let [x, y] = cc.get_many_mut([&a, &b]).unwrap();
+    ///     std::mem::swap(x, y);
+    /// }
+    /// assert_eq!(cc[&a], 2);
+    /// assert_eq!(cc[&b], 1);
+    /// assert!(cc.get_many_mut([&a, &a]).is_err());
+    /// ```
+    pub fn get_many_mut<const N: usize>(&mut self, tickets: [&T; N]) -> Result<[&mut V; N], AccessError> {
+        let mut indices = [0; N];
+        for (i, ticket) in tickets.iter().enumerate() {
+            indices[i] = self.validate(ticket.as_ticket()).map_err(|kind| AccessError { kind: kind })?;
+            // The same ticket can be borrowed more than once.
+            if indices[..i].contains(&indices[i]) {
+                return Err(AccessError { kind: self.reject(ErrorKind::DuplicateTicket) });
+            }
+        }
+        let data = self.data.as_mut_ptr();
+        // Safe because the slots exist (we've handed out the tickets) and are distinct.
+        Ok(indices.map(|index| match unsafe { &mut *data.add(index) } {
+            &mut Full(ref mut v) => v,
+            _ => panic!("forged ticket"),
+        }))
+    }
+
+    /// Get mutable references to the values matching a slice of tickets.
+    ///
+    /// See `CoatCheck::get_many_mut` for details.
+    pub fn get_many_mut_slice(&mut self, tickets: &[&T]) -> Result<Vec<&mut V>, AccessError> {
+        let mut indices = Vec::with_capacity(tickets.len());
+        for ticket in tickets {
+            indices.push(self.validate(ticket.as_ticket()).map_err(|kind| AccessError { kind: kind })?);
+        }
+        let mut sorted = indices.clone();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|w| w[0] == w[1]) {
+            return Err(AccessError { kind: self.reject(ErrorKind::DuplicateTicket) });
+        }
+        let data = self.data.as_mut_ptr();
+        // Safe because the slots exist (we've handed out the tickets) and are distinct.
+        Ok(indices.into_iter().map(|index| match unsafe { &mut *data.add(index) } {
+            &mut Full(ref mut v) => v,
+            _ => panic!("forged ticket"),
+        }).collect())
+    }
+
+    /// Drop all values, invalidating all outstanding tickets.
+    ///
+    /// Using a ticket issued before the coat check was cleared will fail with
+    /// `ErrorKind::Expired`. The coat check keeps its capacity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::{CoatCheck, ErrorKind};
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let ticket = cc.check("a");
+    /// cc.clear();
+    /// assert!(cc.is_empty());
+    /// assert!(match cc.claim(ticket).unwrap_err().kind {
+    ///     ErrorKind::Expired => true,
+    ///     _ => false,
+    /// });
+    /// ```
+    #[inline]
+    pub fn clear(&mut self) {
+        self.drain();
+    }
+
+    /// Remove all values, invalidating all outstanding tickets, and iterate over them.
+    ///
+    /// The coat check is emptied (and all outstanding tickets expire) even if the iterator isn't
+    /// consumed. See `CoatCheck::clear` for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let ticket = cc.check(1);
+    /// assert_eq!(cc.drain().collect::<Vec<_>>(), vec![1]);
+    /// assert!(cc.get(&ticket).is_err());
+    /// ```
+    pub fn drain(&mut self) -> Drain<'_, V> {
+        let remaining = self.available();
+        self.size = 0;
+        self.lent = 0;
+        self.pending = 0;
+        self.moved = 0;
+        self.next_free = 0;
+        // Wrapping would take 2^64 clears.
+        self.epoch = self.epoch.wrapping_add(1);
+        self.locations.clear();
+        leak::expire(self.tag, self.epoch);
+        Drain { walk: Walk::new(self.data.drain(..), (), remaining) }
     }
 }
 
-impl<V> IntoIterator for CoatCheck<V> {
+impl<V, T, O> IntoIterator for CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
     type Item = V;
     type IntoIter = IntoIter<V>;
 
     /// Creates a consuming iterator, that is, one that moves each value out of the coat check (from
     /// start to end). The coat check cannot be used after calling this.
     #[inline]
-    fn into_iter(self) -> IntoIter<V> {
-        IntoIter {
-            remaining: self.len(),
-            inner: self.data.into_iter().filter_map(Entry::<V>::full as fn(Entry<V>) -> Option<V>)
-        }
+    fn into_iter(mut self) -> IntoIter<V> {
+        let remaining = self.available();
+        // The values are moved out (so they aren't reported as still checked in on drop).
+        self.size = 0;
+        let data = mem::replace(&mut self.data, Vec::new());
+        IntoIter { walk: Walk::new(data.into_iter(), (), remaining) }
+    }
+
+}
+
+#[cfg(feature = "leak-check")]
+impl<V, T, O> CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
+    /// List the slots that can no longer be claimed because their tickets were dropped.
+    ///
+    /// Only available with the `leak-check` feature.
+    pub fn leaks(&self) -> Vec<Leak> {
+        self.leaked_slots().into_iter().map(|index| {
+            Leak { index: index, location: self.locations.get(index) }
+        }).collect()
+    }
+}
+
+#[cfg(feature = "leak-check")]
+impl<V, T, O> CoatCheck<V, T, O> {
+    /// The indices of the full slots whose tickets have been dropped.
+    ///
+    /// Dropped tickets only count if their value is still here (it may have been moved out, e.g.,
+    /// by `split_off_by`).
+    fn leaked_slots(&self) -> Vec<usize> {
+        leak::leaked(self.tag, self.epoch).into_iter().filter(|&index| {
+            self.data.get(index).map_or(false, Entry::is_full)
+        }).collect()
     }
+}
 
+/// Report values that are still checked in (and where they were checked in) on drop.
+#[cfg(feature = "leak-check")]
+impl<V, T, O> Drop for CoatCheck<V, T, O> {
+    fn drop(&mut self) {
+        let leaked = self.leaked_slots();
+        leak::unregister(self.tag);
+        if self.size == 0 {
+            return;
+        }
+        eprintln!("coatcheck: dropping a coat check with {} value(s) still checked in:", self.size);
+        for (index, entry) in self.data.iter().enumerate() {
+            if !entry.is_full() {
+                continue;
+            }
+            let status = if leaked.contains(&index) { " (ticket dropped)" } else { "" };
+            match self.locations.get(index) {
+                Some(location) => eprintln!("  slot {} checked at {}{}", index, location, status),
+                None => eprintln!("  slot {}{}", index, status),
+            }
+        }
+    }
 }
 
-impl<V> fmt::Debug for CoatCheck<V> where V: fmt::Debug {
+impl<V, T, O> fmt::Debug for CoatCheck<V, T, O> where V: fmt::Debug, T: TicketType, O: Observer<V> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(write!(f, "{{"));
+        write!(f, "{{")?;
         for (i, v) in self.iter().enumerate() {
-            if i != 0 { try!(write!(f, ", ")); }
-            try!(write!(f, "{:?}", *v));
+            if i != 0 { write!(f, ", ")?; }
+            write!(f, "{:?}", *v)?;
         }
         write!(f, "}}")
     }
 }
 
-impl<'a, V> Index<&'a Ticket> for CoatCheck<V> {
+impl<'a, V, T, O> Index<&'a T> for CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
     type Output = V;
     #[inline]
-    fn index(&self, ticket: &Ticket) -> &V {
-        self.get(ticket).ok().expect("ticket for wrong CoatCheck")
+    fn index(&self, ticket: &T) -> &V {
+        self.get(ticket).unwrap_or_else(|e| panic!("{}", e.kind.description()))
     }
 }
 
-impl<'a, V> IndexMut<&'a Ticket> for CoatCheck<V> {
+impl<'a, V, T, O> IndexMut<&'a T> for CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
     #[inline]
-    fn index_mut(&mut self, ticket: &Ticket) -> &mut V {
-        self.get_mut(ticket).ok().expect("ticket for wrong CoatCheck")
+    fn index_mut(&mut self, ticket: &T) -> &mut V {
+        self.get_mut(ticket).unwrap_or_else(|e| panic!("{}", e.kind.description()))
     }
 }
 
-impl<V> Default for CoatCheck<V> {
+impl<V, T, O> Default for CoatCheck<V, T, O> where T: TicketType, O: Observer<V> + Default {
     #[inline]
     fn default() -> Self {
-        CoatCheck::new()
+        CoatCheck::typed_with_observer(O::default())
     }
 }
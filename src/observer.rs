@@ -0,0 +1,70 @@
+//! Store-level hooks for keeping metrics, logs, and indices in sync with a coat check.
+use {ErrorKind, TicketId};
+
+/// Receives events from a `CoatCheck` (see `CoatCheck::with_observer`).
+///
+/// All methods do nothing by default. The default observer, `()`, ignores everything so coat
+/// checks without an observer don't pay for these hooks.
+///
+/// Observers are called through shared references (`get` can fail without mutable access to the
+/// coat check) so use a `Cell`/`RefCell` to record things.
+///
+/// *Note:* Moving values between coat checks (e.g., `transfer`, `split_off`) shows up as a claim
+/// from one and a check into the other. Values dropped by `clear`, `drain`, or `into_iter` (or
+/// moved out by `absorb`) aren't reported.
+pub trait Observer<V> {
+    /// Called when `value` is checked in under `id`.
+    #[inline]
+    fn on_check(&self, id: &TicketId, value: &V) {
+        let _ = (id, value);
+    }
+
+    /// Called when `value`, checked in under `id`, is claimed.
+    #[inline]
+    fn on_claim(&self, id: &TicketId, value: &V) {
+        let _ = (id, value);
+    }
+
+    /// Called when a ticket is rejected.
+    #[inline]
+    fn on_access_error(&self, kind: ErrorKind) {
+        let _ = kind;
+    }
+}
+
+/// The default observer (ignores everything).
+impl<V> Observer<V> for () {}
+
+impl<'a, V, O: ?Sized> Observer<V> for &'a O where O: Observer<V> {
+    #[inline]
+    fn on_check(&self, id: &TicketId, value: &V) {
+        (**self).on_check(id, value)
+    }
+
+    #[inline]
+    fn on_claim(&self, id: &TicketId, value: &V) {
+        (**self).on_claim(id, value)
+    }
+
+    #[inline]
+    fn on_access_error(&self, kind: ErrorKind) {
+        (**self).on_access_error(kind)
+    }
+}
+
+impl<V, O: ?Sized> Observer<V> for Box<O> where O: Observer<V> {
+    #[inline]
+    fn on_check(&self, id: &TicketId, value: &V) {
+        (**self).on_check(id, value)
+    }
+
+    #[inline]
+    fn on_claim(&self, id: &TicketId, value: &V) {
+        (**self).on_claim(id, value)
+    }
+
+    #[inline]
+    fn on_access_error(&self, kind: ErrorKind) {
+        (**self).on_access_error(kind)
+    }
+}
@@ -0,0 +1,410 @@
+//! Insertion-ordered coat checks.
+//!
+//! A `CoatCheck<V>` reuses freed slots so iterating over it visits values in slot order, not in
+//! the order in which they were checked in. An `OrderedCoatCheck<V>` threads a doubly linked list
+//! through its slots so that iteration follows check order (in both directions) while checking
+//! and claiming stay O(1).
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Index, IndexMut};
+
+use {CoatCheck, Entry, Ticket, ClaimError, AccessError};
+
+/// The end of the list.
+const NONE: usize = usize::MAX;
+
+struct Node<V> {
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+/// A `CoatCheck<V>` that iterates over its values in the order in which they were checked in.
+///
+/// # Examples
+///
+/// ```
+/// use coatcheck::OrderedCoatCheck;
+///
+/// let mut cc = OrderedCoatCheck::new();
+/// let a = cc.check("a");
+/// let b = cc.check("b");
+/// cc.claim(a).unwrap();
+/// // Reuses the slot freed by "a".
+/// let c = cc.check("c");
+///
+/// assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec!["b", "c"]);
+/// assert_eq!(cc.iter().rev().cloned().collect::<Vec<_>>(), vec!["c", "b"]);
+/// # cc.claim(b).unwrap();
+/// # cc.claim(c).unwrap();
+/// ```
+pub struct OrderedCoatCheck<V> {
+    inner: CoatCheck<Node<V>>,
+    head: usize,
+    tail: usize,
+}
+
+impl<V> OrderedCoatCheck<V> {
+    /// Constructs a new, empty `OrderedCoatCheck<T>`.
+    ///
+    /// The coat check will not allocate until elements are pushed onto it.
+    #[inline]
+    pub fn new() -> Self {
+        OrderedCoatCheck::with_capacity(0)
+    }
+
+    /// Constructs a new, empty `OrderedCoatCheck<T>` with the specified capacity.
+    ///
+    /// See `CoatCheck::with_capacity` for details.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        OrderedCoatCheck {
+            inner: CoatCheck::with_capacity(capacity),
+            head: NONE,
+            tail: NONE,
+        }
+    }
+
+    /// Returns the number of elements the coat check can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// The number of checked items.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check if this `OrderedCoatCheck<V>` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Reserves capacity for at least `additional` more elements to be checked into the given
+    /// `OrderedCoatCheck<T>`.
+    ///
+    /// See `CoatCheck::reserve` for details.
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional)
+    }
+
+    /// Check a value in (at the end of the list) and get a `Ticket` in exchange.
+    ///
+    /// *Panics* if the size of the `OrderedCoatCheck<V>` would overflow `usize::MAX`.
+    pub fn check(&mut self, value: V) -> Ticket {
+        let ticket = self.inner.check(Node { value: value, prev: self.tail, next: NONE });
+        let index = ticket.index;
+        match self.tail {
+            NONE => self.head = index,
+            tail => self.node_mut(tail).next = index,
+        }
+        self.tail = index;
+        ticket
+    }
+
+    /// Check if a ticket belongs to this `OrderedCoatCheck<V>`.
+    #[inline]
+    pub fn contains_ticket(&self, ticket: &Ticket) -> bool {
+        self.inner.contains_ticket(ticket)
+    }
+
+    /// Claim an item.
+    ///
+    /// See `CoatCheck::claim` for details.
+    pub fn claim(&mut self, ticket: Ticket) -> Result<V, ClaimError> {
+        let node = self.inner.claim(ticket)?;
+        match node.prev {
+            NONE => self.head = node.next,
+            prev => self.node_mut(prev).next = node.next,
+        }
+        match node.next {
+            NONE => self.tail = node.prev,
+            next => self.node_mut(next).prev = node.prev,
+        }
+        Ok(node.value)
+    }
+
+    /// Get a reference to the value matching this ticket.
+    ///
+    /// See `CoatCheck::get` for details.
+    #[inline]
+    pub fn get(&self, ticket: &Ticket) -> Result<&V, AccessError> {
+        self.inner.get(ticket).map(|node| &node.value)
+    }
+
+    /// Get a mutable reference to the value matching this ticket.
+    ///
+    /// See `CoatCheck::get_mut` for details.
+    #[inline]
+    pub fn get_mut(&mut self, ticket: &Ticket) -> Result<&mut V, AccessError> {
+        self.inner.get_mut(ticket).map(|node| &mut node.value)
+    }
+
+    /// Iterate over the items in this `OrderedCoatCheck<V>` (in check order).
+    #[inline]
+    pub fn iter(&self) -> OrderedIter<'_, V> {
+        OrderedIter {
+            data: &self.inner.data,
+            front: self.head,
+            back: self.tail,
+            remaining: self.len(),
+        }
+    }
+
+    /// Mutably iterate over the items in this `OrderedCoatCheck<V>` (in check order).
+    #[inline]
+    pub fn iter_mut(&mut self) -> OrderedIterMut<'_, V> {
+        OrderedIterMut {
+            data: self.inner.data.as_mut_ptr(),
+            front: self.head,
+            back: self.tail,
+            remaining: self.len(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get the node in a (full) slot.
+    #[inline]
+    fn node_mut(&mut self, index: usize) -> &mut Node<V> {
+        self.inner.data[index].full_mut().expect("corrupt list")
+    }
+}
+
+/// An iterator over the values in an `OrderedCoatCheck<V>` (in check order).
+pub struct OrderedIter<'a, V: 'a> {
+    data: &'a [Entry<Node<V>>],
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a, V> Iterator for OrderedIter<'a, V> {
+    type Item = &'a V;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let node = self.data[self.front].full_ref().expect("corrupt list");
+        self.front = node.next;
+        Some(&node.value)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for OrderedIter<'a, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let node = self.data[self.back].full_ref().expect("corrupt list");
+        self.back = node.prev;
+        Some(&node.value)
+    }
+}
+
+impl<'a, V> ExactSizeIterator for OrderedIter<'a, V> {}
+impl<'a, V> FusedIterator for OrderedIter<'a, V> {}
+
+impl<'a, V> Clone for OrderedIter<'a, V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        OrderedIter { data: self.data, front: self.front, back: self.back, remaining: self.remaining }
+    }
+}
+
+/// A mutable iterator over the values in an `OrderedCoatCheck<V>` (in check order).
+pub struct OrderedIterMut<'a, V: 'a> {
+    data: *mut Entry<Node<V>>,
+    front: usize,
+    back: usize,
+    remaining: usize,
+    _marker: PhantomData<&'a mut V>,
+}
+
+impl<'a, V> OrderedIterMut<'a, V> {
+    /// Get the node at `index`.
+    ///
+    /// Unsafe because the slot must be full and must not be visited twice.
+    #[inline]
+    unsafe fn node(&mut self, index: usize) -> &'a mut Node<V> {
+        (*self.data.add(index)).full_mut().expect("corrupt list")
+    }
+}
+
+impl<'a, V> Iterator for OrderedIterMut<'a, V> {
+    type Item = &'a mut V;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a mut V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Safe because every node in the list is full and we visit each one at most once.
+        let front = self.front;
+        let node = unsafe { self.node(front) };
+        self.front = node.next;
+        Some(&mut node.value)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for OrderedIterMut<'a, V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a mut V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Safe because every node in the list is full and we visit each one at most once.
+        let back = self.back;
+        let node = unsafe { self.node(back) };
+        self.back = node.prev;
+        Some(&mut node.value)
+    }
+}
+
+impl<'a, V> ExactSizeIterator for OrderedIterMut<'a, V> {}
+impl<'a, V> FusedIterator for OrderedIterMut<'a, V> {}
+
+/// A consuming iterator over the values in an `OrderedCoatCheck<V>` (in check order).
+pub struct OrderedIntoIter<V> {
+    data: Vec<Entry<Node<V>>>,
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<V> OrderedIntoIter<V> {
+    #[inline]
+    fn take(&mut self, index: usize) -> Node<V> {
+        mem::replace(&mut self.data[index], Entry::Empty(0)).full().expect("corrupt list")
+    }
+}
+
+impl<V> Iterator for OrderedIntoIter<V> {
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let front = self.front;
+        let node = self.take(front);
+        self.front = node.next;
+        Some(node.value)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<V> DoubleEndedIterator for OrderedIntoIter<V> {
+    #[inline]
+    fn next_back(&mut self) -> Option<V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let back = self.back;
+        let node = self.take(back);
+        self.back = node.prev;
+        Some(node.value)
+    }
+}
+
+impl<V> ExactSizeIterator for OrderedIntoIter<V> {}
+impl<V> FusedIterator for OrderedIntoIter<V> {}
+
+impl<V> IntoIterator for OrderedCoatCheck<V> {
+    type Item = V;
+    type IntoIter = OrderedIntoIter<V>;
+
+    #[inline]
+    fn into_iter(mut self) -> OrderedIntoIter<V> {
+        let remaining = self.len();
+        self.inner.size = 0;
+        OrderedIntoIter {
+            data: mem::replace(&mut self.inner.data, Vec::new()),
+            front: self.head,
+            back: self.tail,
+            remaining: remaining,
+        }
+    }
+}
+
+impl<'a, V> IntoIterator for &'a OrderedCoatCheck<V> {
+    type Item = &'a V;
+    type IntoIter = OrderedIter<'a, V>;
+
+    #[inline]
+    fn into_iter(self) -> OrderedIter<'a, V> {
+        self.iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a mut OrderedCoatCheck<V> {
+    type Item = &'a mut V;
+    type IntoIter = OrderedIterMut<'a, V>;
+
+    #[inline]
+    fn into_iter(self) -> OrderedIterMut<'a, V> {
+        self.iter_mut()
+    }
+}
+
+impl<V> fmt::Debug for OrderedCoatCheck<V> where V: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, v) in self.iter().enumerate() {
+            if i != 0 { write!(f, ", ")?; }
+            write!(f, "{:?}", *v)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<'a, V> Index<&'a Ticket> for OrderedCoatCheck<V> {
+    type Output = V;
+    #[inline]
+    fn index(&self, ticket: &Ticket) -> &V {
+        self.get(ticket).unwrap_or_else(|e| panic!("{}", e.kind.description()))
+    }
+}
+
+impl<'a, V> IndexMut<&'a Ticket> for OrderedCoatCheck<V> {
+    #[inline]
+    fn index_mut(&mut self, ticket: &Ticket) -> &mut V {
+        self.get_mut(ticket).unwrap_or_else(|e| panic!("{}", e.kind.description()))
+    }
+}
+
+impl<V> Default for OrderedCoatCheck<V> {
+    #[inline]
+    fn default() -> Self {
+        OrderedCoatCheck::new()
+    }
+}
@@ -0,0 +1,415 @@
+//! Saving and restoring coat checks.
+//!
+//! Tickets are only meaningful within the process that issued them so they can't be saved.
+//! Instead, `CoatCheck::save` returns a `Snapshot` that can turn tickets into serializable
+//! `Receipt`s. After restoring the coat check with `CoatCheck::load` (possibly in another process),
+//! these receipts can be redeemed (exactly once) for fresh tickets.
+//!
+//! ## Format
+//!
+//! All integers are little endian.
+//!
+//! ```text
+//! magic       b"COATCHCK"
+//! version     u32
+//! snapshot    u64 (random snapshot id)
+//! slots       u64
+//! next_free   u64
+//! size        u64
+//! entries     slots * (0u8 next_free: u64 | 1u8 value)
+//! checksum    u64 (FNV-1a over everything above)
+//! ```
+use std::collections::hash_map::RandomState;
+use std::error::Error as ErrorTrait;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use snowflake::ProcessUniqueId;
+
+use {CoatCheck, Entry, ErrorKind, Ticket, TicketType, Observer, AccessError};
+use Entry::*;
+
+const MAGIC: &'static [u8; 8] = b"COATCHCK";
+const VERSION: u32 = 1;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[inline]
+fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Generate a (probably) unique snapshot id.
+fn snapshot_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(d) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u64(d.as_secs());
+        hasher.write_u32(d.subsec_nanos());
+    }
+    hasher.finish()
+}
+
+/// Checksums everything written through it.
+struct ChecksumWriter<W> {
+    inner: W,
+    hash: u64,
+}
+
+impl<W> Write for ChecksumWriter<W> where W: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hash = fnv(self.hash, &buf[..n]);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W> ChecksumWriter<W> where W: Write {
+    fn write_u64(&mut self, v: u64) -> io::Result<()> {
+        self.write_all(&v.to_le_bytes())
+    }
+}
+
+/// Checksums everything read through it.
+struct ChecksumReader<R> {
+    inner: R,
+    hash: u64,
+}
+
+impl<R> Read for ChecksumReader<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hash = fnv(self.hash, &buf[..n]);
+        Ok(n)
+    }
+}
+
+fn read_u64<R>(r: &mut R) -> io::Result<u64> where R: Read {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_usize<R>(r: &mut R) -> io::Result<usize> where R: Read {
+    let v = read_u64(r)?;
+    if v > usize::MAX as u64 {
+        return Err(invalid("coat check snapshot too large for this platform"));
+    }
+    Ok(v as usize)
+}
+
+/// A saved coat check. Use it to exchange tickets for `Receipt`s.
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+    id: u64,
+    tag: ProcessUniqueId,
+    epoch: u64,
+}
+
+impl Snapshot {
+    /// Get a receipt for a ticket. The receipt can be redeemed for a new ticket (exactly once)
+    /// after restoring the snapshot.
+    ///
+    /// Returns `Err(AccessError)` if the ticket belongs to a coat check other than the saved one
+    /// (`ErrorKind::WrongCoatCheck`) or was issued before the coat check was last cleared
+    /// (`ErrorKind::Expired`).
+    ///
+    /// *Note:* Receipts refer to slots so only ask for receipts for tickets issued before saving.
+    /// A ticket issued afterwards for a slot that was full at the time gets a receipt for the
+    /// slot's saved value.
+    pub fn receipt<T>(&self, ticket: &T) -> Result<Receipt, AccessError> where T: TicketType {
+        let ticket = ticket.as_ticket();
+        if ticket.tag != self.tag {
+            Err(AccessError { kind: ErrorKind::WrongCoatCheck })
+        } else if ticket.epoch != self.epoch {
+            Err(AccessError { kind: ErrorKind::Expired })
+        } else {
+            Ok(Receipt { snapshot: self.id, index: ticket.index as u64 })
+        }
+    }
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Snapshot({:x})", self.id)
+    }
+}
+
+/// A serializable stand-in for a ticket to a value in a saved coat check.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Receipt {
+    snapshot: u64,
+    index: u64,
+}
+
+impl Receipt {
+    /// Serialize this receipt.
+    #[inline]
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.snapshot.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.index.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a receipt.
+    #[inline]
+    pub fn from_bytes(bytes: [u8; 16]) -> Receipt {
+        let mut snapshot = [0u8; 8];
+        let mut index = [0u8; 8];
+        snapshot.copy_from_slice(&bytes[..8]);
+        index.copy_from_slice(&bytes[8..]);
+        Receipt {
+            snapshot: u64::from_le_bytes(snapshot),
+            index: u64::from_le_bytes(index),
+        }
+    }
+}
+
+/// The error yielded when redeeming a receipt fails.
+#[derive(Clone, Copy)]
+pub struct RedeemError {
+    /// The error kind.
+    pub kind: ErrorKind,
+    /// The receipt.
+    pub receipt: Receipt,
+}
+
+impl ErrorTrait for RedeemError {
+    fn description(&self) -> &str {
+        self.kind.description()
+    }
+}
+
+impl fmt::Display for RedeemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RedeemError: {}", self.description())
+    }
+}
+
+impl fmt::Debug for RedeemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Exchanges receipts for tickets to a restored coat check.
+pub struct Redeemer<T = Ticket> {
+    snapshot: u64,
+    tag: ProcessUniqueId,
+    epoch: u64,
+    /// Slots that are full and haven't been redeemed.
+    redeemable: Vec<bool>,
+    _ticket: PhantomData<fn() -> T>,
+}
+
+impl<T> Redeemer<T> where T: TicketType {
+    /// Exchange a receipt for a ticket.
+    ///
+    /// Returns `Err(RedeemError)` with `ErrorKind::WrongSnapshot` if the receipt was issued for
+    /// another snapshot and `ErrorKind::InvalidReceipt` if it has already been redeemed.
+    pub fn redeem(&mut self, receipt: Receipt) -> Result<T, RedeemError> {
+        if receipt.snapshot != self.snapshot {
+            return Err(RedeemError { kind: ErrorKind::WrongSnapshot, receipt: receipt });
+        }
+        let index = receipt.index as usize;
+        match self.redeemable.get_mut(index) {
+            Some(r) if *r && index as u64 == receipt.index => {
+                *r = false;
+                Ok(T::from_ticket(Ticket { tag: self.tag, epoch: self.epoch, index: index }))
+            },
+            _ => Err(RedeemError { kind: ErrorKind::InvalidReceipt, receipt: receipt }),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Redeemer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Redeemer({:x})", self.snapshot)
+    }
+}
+
+impl<V, T, O> CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
+    /// Save this coat check, encoding the values with `encode`.
+    ///
+    /// Returns a `Snapshot` that can be used to exchange tickets for receipts.
+    /// Fails with `io::ErrorKind::InvalidInput` if any values are on loan (see `CoatCheck::lend`) or
+    /// any reserved slots haven't been filled (see `CoatCheck::reserve_slot`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use coatcheck::{CoatCheck, Ticket};
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let ticket = cc.check(42u32);
+    ///
+    /// let mut buf = Vec::new();
+    /// let snapshot = cc.save(&mut buf, |v, w| w.write_all(&v.to_le_bytes())).unwrap();
+    /// let receipt = snapshot.receipt(&ticket).unwrap();
+    ///
+    /// // Later...
+    /// let (mut restored, mut redeemer) = CoatCheck::<u32>::load(&buf[..], |r| {
+    ///     let mut bytes = [0u8; 4];
+    ///     r.read_exact(&mut bytes).map(|_| u32::from_le_bytes(bytes))
+    /// }).unwrap();
+    /// let ticket: Ticket = redeemer.redeem(receipt).unwrap();
+    /// assert_eq!(restored.claim(ticket).unwrap(), 42);
+    /// ```
+    pub fn save<W, F>(&self, writer: W, mut encode: F) -> io::Result<Snapshot> where
+        W: Write,
+        F: FnMut(&V, &mut dyn Write) -> io::Result<()>
+    {
+        if self.lent != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "coat check has values on loan"));
+        }
+        if self.pending != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "coat check has unfilled slots"));
+        }
+        let id = snapshot_id();
+        let mut w = ChecksumWriter { inner: writer, hash: FNV_OFFSET };
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_u64(id)?;
+        w.write_u64(self.data.len() as u64)?;
+        w.write_u64(self.next_free as u64)?;
+        w.write_u64(self.size as u64)?;
+        for entry in &self.data {
+            match entry {
+                &Empty(next_free) => {
+                    w.write_all(&[0])?;
+                    w.write_u64(next_free as u64)?;
+                },
+                &Full(ref value) => {
+                    w.write_all(&[1])?;
+                    encode(value, &mut w)?;
+                },
+                &Moved => w.write_all(&[2])?,
+                &Lent | &Reserved => unreachable!(),
+            }
+        }
+        let checksum = w.hash;
+        w.inner.write_all(&checksum.to_le_bytes())?;
+        w.flush()?;
+        Ok(Snapshot { id: id, tag: self.tag, epoch: self.epoch })
+    }
+}
+
+impl<V, T> CoatCheck<V, T> where T: TicketType {
+    /// Restore a coat check saved with `save`, decoding the values with `decode`.
+    ///
+    /// Returns the restored coat check and a `Redeemer` for exchanging receipts for tickets.
+    /// Fails with `io::ErrorKind::InvalidData` if the data is corrupt or was written by an
+    /// unsupported version.
+    pub fn load<R, F>(reader: R, mut decode: F) -> io::Result<(Self, Redeemer<T>)> where
+        R: Read,
+        F: FnMut(&mut dyn Read) -> io::Result<V>
+    {
+        let mut r = ChecksumReader { inner: reader, hash: FNV_OFFSET };
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a coat check snapshot"));
+        }
+        let mut version = [0u8; 4];
+        r.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != VERSION {
+            return Err(invalid("unsupported coat check snapshot version"));
+        }
+        let id = read_u64(&mut r)?;
+        let len = read_usize(&mut r)?;
+        let next_free = read_usize(&mut r)?;
+        let size = read_usize(&mut r)?;
+        if next_free > len || size > len {
+            return Err(invalid("corrupt coat check snapshot"));
+        }
+
+        // Don't trust `len` for preallocation; the data may be truncated.
+        let mut data = Vec::new();
+        let mut redeemable = Vec::new();
+        let mut moved = Vec::new();
+        for _ in 0..len {
+            let mut tag = [0u8; 1];
+            r.read_exact(&mut tag)?;
+            match tag[0] {
+                0 => {
+                    let next = read_usize(&mut r)?;
+                    if next > len {
+                        return Err(invalid("corrupt coat check snapshot"));
+                    }
+                    data.push(Empty(next));
+                    redeemable.push(false);
+                },
+                1 => {
+                    data.push(Full(decode(&mut r)?));
+                    redeemable.push(true);
+                },
+                // No tickets for the restored coat check can refer to a moved value so these
+                // slots are freed below.
+                2 => {
+                    moved.push(data.len());
+                    data.push(Moved);
+                    redeemable.push(false);
+                },
+                _ => return Err(invalid("corrupt coat check snapshot")),
+            }
+        }
+
+        let checksum = r.hash;
+        if read_u64(&mut r.inner)? != checksum {
+            return Err(invalid("coat check snapshot checksum mismatch"));
+        }
+        let mut next_free = next_free;
+        for index in moved {
+            data[index] = Empty(next_free);
+            next_free = index;
+        }
+        if !free_list_valid(&data, next_free, size) {
+            return Err(invalid("corrupt coat check snapshot"));
+        }
+
+        let mut cc = CoatCheck::typed();
+        cc.data = data;
+        cc.next_free = next_free;
+        cc.size = size;
+        let redeemer = Redeemer {
+            snapshot: id,
+            tag: cc.tag,
+            epoch: cc.epoch,
+            redeemable: redeemable,
+            _ticket: PhantomData,
+        };
+        Ok((cc, redeemer))
+    }
+}
+
+/// Check that the free list visits every empty slot exactly once and that `size` matches.
+fn free_list_valid<V>(data: &[Entry<V>], next_free: usize, size: usize) -> bool {
+    let empty = data.len() - size;
+    let mut seen = 0;
+    let mut next = next_free;
+    while next != data.len() {
+        match data[next] {
+            // Stop once we've seen more empty slots than exist (a cycle).
+            Empty(n) if seen < empty => next = n,
+            _ => return false,
+        }
+        seen += 1;
+    }
+    seen == empty && data.iter().filter(|e| e.is_full()).count() == size
+}
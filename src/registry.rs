@@ -0,0 +1,295 @@
+//! A ready-made callback registry (the `System` from the crate documentation).
+//!
+//! Callbacks can add and remove callbacks (including themselves) while the registry is firing:
+//! callbacks added while firing are first called by the next `fire` and callbacks removed while
+//! firing won't be called again, not even by the current `fire`.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//! use coatcheck::registry::Registry;
+//!
+//! let registry = Rc::new(Registry::new());
+//! let fired = Rc::new(Cell::new(0));
+//!
+//! let f = fired.clone();
+//! let handle = registry.add(move |n: &u32| f.set(f.get() + n));
+//!
+//! let r = registry.clone();
+//! let _once = registry.add_once(move |_: &u32| {
+//!     // Safe to call back into the registry.
+//!     r.add(|_: &u32| ());
+//! });
+//!
+//! registry.fire(&2);
+//! assert_eq!(fired.get(), 2);
+//! assert_eq!(registry.len(), 2);
+//!
+//! registry.remove(handle).unwrap();
+//! registry.fire(&2);
+//! assert_eq!(fired.get(), 2);
+//! ```
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use snowflake::ProcessUniqueId;
+
+use {CoatCheck, ClaimError, ErrorKind, RawTicket, Ticket};
+
+/// A handle for removing a callback from a `Registry`.
+///
+/// Handles for one-shot callbacks outlive their callbacks. The registration number tells a spent
+/// one-shot callback's handle apart from the handle of a newer callback in the same slot.
+#[must_use = "you need this handle to remove the callback"]
+pub struct Handle {
+    tag: ProcessUniqueId,
+    raw: RawTicket,
+    seq: u64,
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle")
+    }
+}
+
+impl From<ClaimError<Handle>> for Handle {
+    fn from(e: ClaimError<Handle>) -> Handle {
+        e.ticket
+    }
+}
+
+type Callback<A> = Box<dyn FnMut(&A)>;
+
+struct Entry<A: ?Sized> {
+    /// The callback (taken out while it's being fired).
+    callback: Option<Callback<A>>,
+    priority: i32,
+    /// Registration order.
+    seq: u64,
+    /// The entry's raw ticket (one-shot callbacks only), for claiming the callback once it's been
+    /// called.
+    once: Option<RawTicket>,
+}
+
+/// A callback taken out of the registry while firing.
+struct Pending<A: ?Sized> {
+    priority: i32,
+    seq: u64,
+    called: bool,
+    callback: Callback<A>,
+}
+
+/// A set of callbacks taking `&A`.
+pub struct Registry<A: ?Sized> {
+    callbacks: RefCell<CoatCheck<Entry<A>>>,
+    next_seq: Cell<u64>,
+    /// Callbacks removed while being fired.
+    cancelled: RefCell<Vec<u64>>,
+    /// The number of `fire` calls in progress.
+    depth: Cell<usize>,
+}
+
+impl<A: ?Sized> Registry<A> {
+    /// Constructs a new, empty `Registry<A>`.
+    #[inline]
+    pub fn new() -> Self {
+        Registry {
+            callbacks: RefCell::new(CoatCheck::new()),
+            next_seq: Cell::new(0),
+            cancelled: RefCell::new(Vec::new()),
+            depth: Cell::new(0),
+        }
+    }
+
+    /// The number of registered callbacks.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.callbacks.borrow().len()
+    }
+
+    /// Returns `true` if no callbacks are registered.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Register a callback.
+    ///
+    /// Returns a handle for removing the callback.
+    #[inline]
+    pub fn add<F>(&self, callback: F) -> Handle where F: FnMut(&A) + 'static {
+        self.add_with_priority(0, callback)
+    }
+
+    /// Register a callback with a priority.
+    ///
+    /// Callbacks with higher priorities are called first; callbacks with the same priority are
+    /// called in the order in which they were registered (`add` uses priority 0).
+    #[inline]
+    pub fn add_with_priority<F>(&self, priority: i32, callback: F) -> Handle where
+        F: FnMut(&A) + 'static
+    {
+        self.insert(priority, Box::new(callback))
+    }
+
+    /// Register a callback to be called (at most) once.
+    ///
+    /// The callback is removed after it's called. Returns a handle for removing the callback before
+    /// it's called (afterwards, `remove` rejects the handle).
+    #[inline]
+    pub fn add_once<F>(&self, callback: F) -> Handle where F: FnOnce(&A) + 'static {
+        self.add_once_with_priority(0, callback)
+    }
+
+    /// Register a callback to be called (at most) once, with a priority.
+    ///
+    /// See `Registry::add_once` and `Registry::add_with_priority` for details.
+    pub fn add_once_with_priority<F>(&self, priority: i32, callback: F) -> Handle where
+        F: FnOnce(&A) + 'static
+    {
+        let mut callback = Some(callback);
+        let handle = self.insert(priority, Box::new(move |args: &A| {
+            if let Some(callback) = callback.take() {
+                callback(args)
+            }
+        }));
+        // Keep a copy of the raw ticket to claim the callback once it's been called.
+        let mut callbacks = self.callbacks.borrow_mut();
+        // Safe because the ticket was just turned into this raw ticket (and is turned right back).
+        let ticket: Ticket = unsafe { callbacks.redeem_raw_unchecked(handle.raw) };
+        callbacks.get_mut(&ticket).ok().expect("just registered").once = Some(handle.raw);
+        let _ = ticket.into_raw();
+        handle
+    }
+
+    fn insert(&self, priority: i32, callback: Callback<A>) -> Handle {
+        let seq = self.next_seq.get();
+        self.next_seq.set(seq + 1);
+        let mut callbacks = self.callbacks.borrow_mut();
+        let ticket = callbacks.check(Entry {
+            callback: Some(callback),
+            priority: priority,
+            seq: seq,
+            once: None,
+        });
+        Handle { tag: callbacks.tag, raw: ticket.into_raw(), seq: seq }
+    }
+
+    /// Remove a callback.
+    ///
+    /// Returns `Err(ClaimError)` if the handle belongs to another registry or to a one-shot
+    /// callback that has already been called (returning the handle inside of the ClaimError).
+    /// The error kind is `ErrorKind::Stale` if a newer callback has taken the spent callback's
+    /// slot.
+    pub fn remove(&self, handle: Handle) -> Result<(), ClaimError<Handle>> {
+        let entry = {
+            let mut callbacks = self.callbacks.borrow_mut();
+            if handle.tag != callbacks.tag {
+                return Err(ClaimError { kind: ErrorKind::WrongCoatCheck, ticket: handle });
+            }
+            // Handles for one-shot callbacks outlive their callbacks so check that the slot still
+            // holds the handle's callback.
+            let ticket: Ticket = match callbacks.redeem_raw(handle.raw) {
+                Ok(ticket) => ticket,
+                Err(e) => return Err(ClaimError { kind: e.kind, ticket: handle }),
+            };
+            if callbacks[&ticket].seq != handle.seq {
+                // A newer callback took over the slot (keep its ticket unique).
+                let _ = ticket.into_raw();
+                return Err(ClaimError { kind: ErrorKind::Stale, ticket: handle });
+            }
+            callbacks.claim(ticket).ok().expect("redeemed handle")
+        };
+        if entry.callback.is_none() {
+            // It's being fired. Make sure it isn't called again.
+            self.cancelled.borrow_mut().push(entry.seq);
+        }
+        Ok(())
+    }
+
+    /// Call all registered callbacks (highest priority first).
+    ///
+    /// Callbacks may call back into the registry. A callback that fires the registry again skips
+    /// the callbacks that are already running.
+    pub fn fire(&self, args: &A) {
+        let mut pending: Vec<_> = self.callbacks.borrow_mut().iter_mut().filter_map(|entry| {
+            let (priority, seq) = (entry.priority, entry.seq);
+            entry.callback.take().map(|callback| Pending {
+                priority: priority,
+                seq: seq,
+                called: false,
+                callback: callback,
+            })
+        }).collect();
+        pending.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.seq.cmp(&b.seq)));
+
+        self.depth.set(self.depth.get() + 1);
+        // Puts the callbacks back, even if one of them panics.
+        let mut firing = Firing { registry: self, pending: pending };
+        for i in 0..firing.pending.len() {
+            let seq = firing.pending[i].seq;
+            if self.cancelled.borrow().contains(&seq) {
+                continue;
+            }
+            let callback = &mut firing.pending[i];
+            callback.called = true;
+            (callback.callback)(args);
+        }
+    }
+}
+
+struct Firing<'a, A: ?Sized + 'a> {
+    registry: &'a Registry<A>,
+    pending: Vec<Pending<A>>,
+}
+
+impl<'a, A: ?Sized> Drop for Firing<'a, A> {
+    fn drop(&mut self) {
+        let mut pending: BTreeMap<u64, Pending<A>> = self.pending.drain(..).map(|p| (p.seq, p)).collect();
+        let mut spent = Vec::new();
+        let mut dead = Vec::new();
+        {
+            let mut callbacks = self.registry.callbacks.borrow_mut();
+            for entry in callbacks.iter_mut() {
+                if let Some(p) = pending.remove(&entry.seq) {
+                    if p.called && entry.once.is_some() {
+                        spent.extend(entry.once.take());
+                        dead.push(p.callback);
+                    } else {
+                        entry.callback = Some(p.callback);
+                    }
+                }
+            }
+            for raw in spent {
+                let ticket: Ticket = callbacks.redeem_raw(raw).ok().expect("live one-shot callback");
+                let _ = callbacks.claim(ticket);
+            }
+        }
+        let depth = self.registry.depth.get() - 1;
+        self.registry.depth.set(depth);
+        if depth == 0 {
+            self.registry.cancelled.borrow_mut().clear();
+        }
+        // Drop the spent and removed callbacks without holding any borrows (their destructors
+        // might use the registry).
+        drop(dead);
+        drop(pending);
+    }
+}
+
+impl<A: ?Sized> fmt::Debug for Registry<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Registry({})", self.len())
+    }
+}
+
+impl<A: ?Sized> Default for Registry<A> {
+    #[inline]
+    fn default() -> Self {
+        Registry::new()
+    }
+}
@@ -0,0 +1,325 @@
+//! Moving and copying values between coat checks in bulk.
+//!
+//! When values are moved from one coat check to another in bulk, their old tickets can't be
+//! updated in place. Instead, these operations return a `TicketRemap` that exchanges old tickets
+//! for tickets to the values' new home. Similarly, cloning a coat check yields a `TicketMap` that
+//! hands out tickets to the clone's values in exchange for (references to) the original tickets.
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+
+use snowflake::ProcessUniqueId;
+
+use {CoatCheck, Entry, Ticket, TicketId, TicketType, Observer, ClaimError, AccessError, ErrorKind, leak};
+use Entry::*;
+
+/// Exchanges tickets for moved values for tickets to their new coat check.
+#[must_use = "you need the remap to exchange your old tickets"]
+pub struct TicketRemap<T = Ticket> {
+    /// The coat check the tickets were issued by.
+    from_tag: ProcessUniqueId,
+    from_epoch: u64,
+    /// The coat check the values were moved to.
+    to_tag: ProcessUniqueId,
+    to_epoch: u64,
+    /// New index by old index (`None` if the value wasn't moved or has been exchanged).
+    indices: Vec<Option<usize>>,
+    _ticket: PhantomData<fn(T) -> T>,
+}
+
+impl<T> TicketRemap<T> where T: TicketType {
+    fn new<A, B, P, Q>(from: &CoatCheck<A, T, P>, to: &CoatCheck<B, T, Q>, indices: Vec<Option<usize>>) -> Self {
+        TicketRemap {
+            from_tag: from.tag,
+            from_epoch: from.epoch,
+            to_tag: to.tag,
+            to_epoch: to.epoch,
+            indices: indices,
+            _ticket: PhantomData,
+        }
+    }
+
+    /// Exchange a ticket for a moved value for a ticket to its new coat check.
+    ///
+    /// Returns `Ok(ticket)` if the ticket's value was moved (eating the ticket).
+    /// Returns `Err(ClaimError)` (returning the ticket inside of the ClaimError) with
+    /// `ErrorKind::WrongCoatCheck` if the ticket wasn't issued by the coat check the values were
+    /// moved out of, `ErrorKind::Expired` if it was issued before that coat check was last
+    /// cleared, and `ErrorKind::InvalidTicket` if its value wasn't moved.
+    pub fn exchange(&mut self, ticket: T) -> Result<T, ClaimError<T>> {
+        let index = {
+            let t = ticket.as_ticket();
+            if t.tag != self.from_tag {
+                return Err(ClaimError { ticket: ticket, kind: ErrorKind::WrongCoatCheck });
+            }
+            if t.epoch != self.from_epoch {
+                return Err(ClaimError { ticket: ticket, kind: ErrorKind::Expired });
+            }
+            t.index
+        };
+        match self.indices.get_mut(index).and_then(Option::take) {
+            Some(new_index) => {
+                ticket.into_ticket().consume();
+                Ok(T::from_ticket(Ticket { tag: self.to_tag, epoch: self.to_epoch, index: new_index }))
+            },
+            None => Err(ClaimError { ticket: ticket, kind: ErrorKind::InvalidTicket }),
+        }
+    }
+
+    /// The number of tickets that have yet to be exchanged.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.indices.iter().filter(|i| i.is_some()).count()
+    }
+
+    /// Check if all tickets have been exchanged.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> fmt::Debug for TicketRemap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TicketRemap")
+    }
+}
+
+/// Hands out tickets for the values in a cloned coat check (see `CoatCheck::clone_with_map`).
+#[must_use = "you need the map to get tickets for the clone"]
+pub struct TicketMap<T = Ticket> {
+    /// The original coat check.
+    from_tag: ProcessUniqueId,
+    from_epoch: u64,
+    /// The clone.
+    to_tag: ProcessUniqueId,
+    to_epoch: u64,
+    /// Slots (same index in both coat checks) that have yet to be handed out.
+    unclaimed: Vec<bool>,
+    _ticket: PhantomData<fn(T) -> T>,
+}
+
+impl<T> TicketMap<T> where T: TicketType {
+    /// Get a ticket for the clone of the value matching this ticket (to the original).
+    ///
+    /// Each ticket can only be translated once.
+    ///
+    /// Returns `Ok(ticket)` if the ticket was issued by the original coat check.
+    /// Returns `Err(AccessError)` with `ErrorKind::WrongCoatCheck` if the ticket belongs to
+    /// another coat check, `ErrorKind::Expired` if it was issued before the original was last
+    /// cleared, and `ErrorKind::InvalidTicket` if it was issued after cloning or has already been
+    /// translated.
+    ///
+    /// *Note:* Translate tickets before claiming other values from the original. A ticket for a
+    /// value checked into a slot freed after cloning looks just like the ticket for the slot's old
+    /// value and is translated to the clone of the old value.
+    pub fn translate(&mut self, ticket: &T) -> Result<T, AccessError> {
+        let ticket = ticket.as_ticket();
+        if ticket.tag != self.from_tag {
+            return Err(AccessError { kind: ErrorKind::WrongCoatCheck });
+        }
+        if ticket.epoch != self.from_epoch {
+            return Err(AccessError { kind: ErrorKind::Expired });
+        }
+        match self.unclaimed.get_mut(ticket.index) {
+            Some(unclaimed) if *unclaimed => {
+                *unclaimed = false;
+                Ok(T::from_ticket(Ticket { tag: self.to_tag, epoch: self.to_epoch, index: ticket.index }))
+            },
+            _ => Err(AccessError { kind: ErrorKind::InvalidTicket }),
+        }
+    }
+
+    /// The number of tickets that have yet to be translated.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.unclaimed.iter().filter(|&&u| u).count()
+    }
+
+    /// Check if all tickets have been translated.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> fmt::Debug for TicketMap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TicketMap")
+    }
+}
+
+impl<V, T, O> CoatCheck<V, T, O> where V: Clone, T: TicketType, O: Observer<V> {
+    /// Clone this coat check.
+    ///
+    /// The clone has its own tag so tickets for this coat check don't work with the clone.
+    /// Instead, use the returned `TicketMap` to get tickets for the clone.
+    ///
+    /// Values on loan aren't cloned (loans can only be restored to this coat check) and neither are
+    /// reserved slots (see `CoatCheck::reserve_slot`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut world = CoatCheck::new();
+    /// let player = world.check(10);
+    ///
+    /// let (mut fork, mut map) = world.clone_with_map();
+    /// let forked_player = map.translate(&player).unwrap();
+    /// fork[&forked_player] += 5;
+    ///
+    /// assert_eq!(world[&player], 10);
+    /// assert_eq!(fork[&forked_player], 15);
+    /// ```
+    pub fn clone_with_map(&self) -> (CoatCheck<V, T>, TicketMap<T>) {
+        let mut clone: CoatCheck<V, T> = CoatCheck::typed_with_capacity(self.data.len());
+        clone.next_free = self.next_free;
+        clone.size = self.size;
+        for (index, entry) in self.data.iter().enumerate() {
+            clone.data.push(match entry {
+                &Full(ref value) => Full(value.clone()),
+                &Empty(next_free) => Empty(next_free),
+                // Loans (and deposits) can only be restored to (filled in) the original so the
+                // clone frees their slots.
+                &Lent | &Reserved => {
+                    let entry = Empty(clone.next_free);
+                    clone.next_free = index;
+                    clone.size -= 1;
+                    entry
+                },
+                // Nothing can refer to the clone's slot so there's no need to keep it off of the
+                // free list.
+                &Moved => {
+                    let entry = Empty(clone.next_free);
+                    clone.next_free = index;
+                    entry
+                },
+            });
+        }
+        let map = TicketMap {
+            from_tag: self.tag,
+            from_epoch: self.epoch,
+            to_tag: clone.tag,
+            to_epoch: clone.epoch,
+            unclaimed: self.data.iter().map(Entry::is_full).collect(),
+            _ticket: PhantomData,
+        };
+        (clone, map)
+    }
+}
+
+impl<V, T, O> CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
+    /// Move all values out of `other` and into this coat check.
+    ///
+    /// Returns a `TicketRemap` that exchanges `other`'s tickets for tickets to this coat check.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut a = CoatCheck::new();
+    /// let mut b = CoatCheck::new();
+    /// let ta = a.check("a");
+    /// let tb = b.check("b");
+    ///
+    /// let mut remap = a.absorb(b);
+    /// let tb = remap.exchange(tb).unwrap();
+    /// assert_eq!(a[&ta], "a");
+    /// assert_eq!(a[&tb], "b");
+    ///
+    /// // `ta` wasn't part of the merge.
+    /// assert!(remap.exchange(ta).is_err());
+    /// ```
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn absorb<P>(&mut self, mut other: CoatCheck<V, T, P>) -> TicketRemap<T> where P: Observer<V> {
+        let caller = leak::caller();
+        self.reserve(other.len());
+        other.size = 0;
+        let data = mem::replace(&mut other.data, Vec::new());
+        let indices = data.into_iter().map(|entry| {
+            entry.full().map(|value| self.check_at(value, caller).into_ticket().into_index())
+        }).collect();
+        TicketRemap::new(&other, self, indices)
+    }
+
+    /// Move the values matching a predicate out of this coat check and into a new one.
+    ///
+    /// Returns the new coat check and a `TicketRemap` that exchanges tickets for moved values for
+    /// tickets to the new coat check. Tickets for the remaining values stay valid.
+    ///
+    /// Using a ticket for a moved value with this coat check fails with `ErrorKind::Moved`. To keep
+    /// it that way, the moved values' slots aren't reused until this coat check is cleared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let one = cc.check(1);
+    /// let two = cc.check(2);
+    ///
+    /// let (mut evens, mut remap) = cc.split_off_by(|v| v % 2 == 0);
+    /// let two = remap.exchange(two).unwrap();
+    /// assert_eq!(evens[&two], 2);
+    /// assert_eq!(cc[&one], 1);
+    /// ```
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn split_off_by<F>(&mut self, mut f: F) -> (CoatCheck<V, T>, TicketRemap<T>) where
+        F: FnMut(&V) -> bool
+    {
+        let indices: Vec<usize> = self.data.iter().enumerate().filter(|&(_, entry)| {
+            entry.full_ref().map_or(false, &mut f)
+        }).map(|(i, _)| i).collect();
+        self.split_off_indices(indices, leak::caller())
+    }
+
+    /// Move the values matching some tickets out of this coat check and into a new one.
+    ///
+    /// Returns the new coat check and a `TicketRemap` that exchanges the tickets for tickets to the
+    /// new coat check. Tickets for the remaining values stay valid (see
+    /// `CoatCheck::split_off_by` for what happens to the moved values' tickets).
+    ///
+    /// Returns `Err(AccessError)` (moving nothing) if any of the tickets belongs to another coat
+    /// check or was issued before this coat check was last cleared.
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn split_off<'a, I>(&mut self, tickets: I) -> Result<(CoatCheck<V, T>, TicketRemap<T>), AccessError> where
+        I: IntoIterator<Item=&'a T>,
+        T: 'a
+    {
+        let mut indices = Vec::new();
+        for ticket in tickets {
+            indices.push(self.validate(ticket.as_ticket()).map_err(|kind| AccessError { kind: kind })?);
+        }
+        // The same ticket may have been passed more than once.
+        indices.sort_unstable();
+        indices.dedup();
+        Ok(self.split_off_indices(indices, leak::caller()))
+    }
+
+    /// Move the values at the given (full, distinct) indices into a new coat check.
+    fn split_off_indices(&mut self, indices: Vec<usize>, caller: leak::Caller) -> (CoatCheck<V, T>, TicketRemap<T>) {
+        let mut other: CoatCheck<V, T> = CoatCheck::typed_with_capacity(indices.len());
+        let mut map = Vec::new();
+        for index in indices {
+            // Safe because the indices came from valid tickets or full slots. Leave a tombstone
+            // behind so that the value's old tickets can't refer to a new value.
+            let value = match mem::replace(unsafe { self.data.get_unchecked_mut(index) }, Moved) {
+                Full(value) => value,
+                _ => unreachable!(),
+            };
+            self.size -= 1;
+            self.moved += 1;
+            self.observer.on_claim(&TicketId { tag: self.tag, epoch: self.epoch, index: index }, &value);
+            if index >= map.len() {
+                map.resize(index + 1, None);
+            }
+            map[index] = Some(other.check_at(value, caller).into_ticket().into_index());
+        }
+        let remap = TicketRemap::new(self, &other, map);
+        (other, remap)
+    }
+}
@@ -0,0 +1,156 @@
+//! Per-value data stored outside of a coat check.
+//!
+//! A `SecondaryMap<U>` attaches extra data to the values in a `WeakCoatCheck` (its primary) by
+//! slot index. Entries are keyed by `WeakTicket`s so each entry remembers the generation of the
+//! slot it was inserted for and, once the value is claimed and its slot reused, the entry is
+//! recognized as stale instead of being handed out for the new value.
+use std::fmt;
+
+use snowflake::ProcessUniqueId;
+
+use {WeakCoatCheck, WeakTicket, AccessError, ErrorKind};
+
+/// A map from weak tickets (for a single `WeakCoatCheck`) to values of type `U`.
+///
+/// # Examples
+///
+/// ```
+/// use coatcheck::{WeakCoatCheck, SecondaryMap};
+///
+/// let mut cc = WeakCoatCheck::new();
+/// let mut names = SecondaryMap::new(&cc);
+///
+/// let ticket = cc.check(42);
+/// names.insert(&cc.weak(&ticket), "answer").unwrap();
+/// assert_eq!(names.get(&cc.weak(&ticket)).unwrap(), Some(&"answer"));
+///
+/// cc.claim(ticket).unwrap();
+/// let ticket = cc.check(7); // Reuses the slot.
+/// assert_eq!(names.get(&cc.weak(&ticket)).unwrap(), None);
+/// ```
+pub struct SecondaryMap<U> {
+    tag: ProcessUniqueId,
+    /// (Generation, value) by slot index.
+    slots: Vec<Option<(u64, U)>>,
+    len: usize,
+}
+
+impl<U> SecondaryMap<U> {
+    /// Constructs a new, empty `SecondaryMap<U>` for weak tickets issued by `primary`.
+    #[inline]
+    pub fn new<V>(primary: &WeakCoatCheck<V>) -> Self {
+        SecondaryMap {
+            tag: primary.tag(),
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// The number of entries (including stale entries that have yet to be pruned).
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if this `SecondaryMap<U>` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check that a weak ticket was issued by the primary, returning its index and generation.
+    #[inline]
+    fn validate(&self, weak: &WeakTicket) -> Result<(usize, u64), AccessError> {
+        if weak.tag != self.tag {
+            Err(AccessError { kind: ErrorKind::WrongCoatCheck })
+        } else {
+            Ok((weak.index, weak.generation))
+        }
+    }
+
+    /// Associate a value with a weak ticket.
+    ///
+    /// Returns `Ok(Some(old_value))` if the weak ticket already had a value (replacing it) and
+    /// `Ok(None)` otherwise (replacing any stale entry).
+    /// Returns `Err(AccessError)` if the weak ticket wasn't issued by the primary.
+    pub fn insert(&mut self, weak: &WeakTicket, value: U) -> Result<Option<U>, AccessError> {
+        let (index, generation) = self.validate(weak)?;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        match self.slots[index].replace((generation, value)) {
+            Some((old_generation, old_value)) => {
+                Ok(if old_generation == generation { Some(old_value) } else { None })
+            },
+            None => {
+                self.len += 1;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Get a reference to the value associated with this weak ticket.
+    ///
+    /// Returns `Ok(None)` if the weak ticket has no (live) value.
+    /// Returns `Err(AccessError)` if the weak ticket wasn't issued by the primary.
+    pub fn get(&self, weak: &WeakTicket) -> Result<Option<&U>, AccessError> {
+        let (index, generation) = self.validate(weak)?;
+        Ok(match self.slots.get(index) {
+            Some(&Some((g, ref value))) if g == generation => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Get a mutable reference to the value associated with this weak ticket.
+    ///
+    /// See `SecondaryMap::get` for details.
+    pub fn get_mut(&mut self, weak: &WeakTicket) -> Result<Option<&mut U>, AccessError> {
+        let (index, generation) = self.validate(weak)?;
+        Ok(match self.slots.get_mut(index) {
+            Some(&mut Some((g, ref mut value))) if g == generation => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Remove the value associated with this weak ticket.
+    ///
+    /// Returns `Ok(Some(value))` if the weak ticket had a value and `Ok(None)` otherwise.
+    /// Returns `Err(AccessError)` if the weak ticket wasn't issued by the primary.
+    pub fn remove(&mut self, weak: &WeakTicket) -> Result<Option<U>, AccessError> {
+        let (index, generation) = self.validate(weak)?;
+        match self.slots.get_mut(index) {
+            Some(slot) if slot.as_ref().map_or(false, |&(g, _)| g == generation) => {
+                self.len -= 1;
+                Ok(slot.take().map(|(_, value)| value))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Drop all stale entries (entries whose values are no longer checked into the primary).
+    ///
+    /// *Panics* if `primary` isn't the coat check this map was created for.
+    pub fn prune<V>(&mut self, primary: &WeakCoatCheck<V>) {
+        assert!(primary.tag() == self.tag, "wrong primary coat check");
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let live = match *slot {
+                Some((generation, _)) => primary.is_live(&WeakTicket {
+                    tag: self.tag,
+                    index: index,
+                    generation: generation,
+                }),
+                None => continue,
+            };
+            if !live {
+                *slot = None;
+                self.len -= 1;
+            }
+        }
+    }
+}
+
+impl<U> fmt::Debug for SecondaryMap<U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecondaryMap({})", self.len)
+    }
+}
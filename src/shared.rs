@@ -0,0 +1,193 @@
+//! Shared coat checks and guard tickets.
+//!
+//! A `SharedCoatCheck<V>` is a reference counted, `RefCell`-wrapped coat check. It can hand out
+//! `Guard`s: tickets that claim (and drop) their value when dropped.
+use std::cell::{Ref, RefCell, RefMut};
+use std::fmt;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use {CoatCheck, Ticket, TicketType, ClaimError};
+
+struct Shared<V, T> {
+    cc: RefCell<CoatCheck<V, T>>,
+    /// Tickets from guards dropped while the coat check was borrowed.
+    deferred: RefCell<Vec<T>>,
+}
+
+/// A shared (reference counted) coat check.
+///
+/// Cloning a `SharedCoatCheck` yields a new handle to the same coat check.
+///
+/// # Examples
+///
+/// ```
+/// use coatcheck::SharedCoatCheck;
+///
+/// let cc = SharedCoatCheck::new();
+/// {
+///     let _guard = cc.check_guarded("subscription");
+///     assert_eq!(cc.borrow().len(), 1);
+/// }
+/// // The guard claimed its value when dropped.
+/// assert!(cc.borrow().is_empty());
+/// ```
+pub struct SharedCoatCheck<V, T = Ticket> {
+    inner: Rc<Shared<V, T>>,
+}
+
+impl<V> SharedCoatCheck<V> {
+    /// Constructs a new, empty `SharedCoatCheck<V>`.
+    #[inline]
+    pub fn new() -> Self {
+        SharedCoatCheck::from(CoatCheck::new())
+    }
+}
+
+impl<V, T> SharedCoatCheck<V, T> where T: TicketType {
+    /// Borrow the coat check.
+    ///
+    /// *Panics* if the coat check is currently mutably borrowed.
+    #[inline]
+    pub fn borrow(&self) -> Ref<'_, CoatCheck<V, T>> {
+        self.inner.cc.borrow()
+    }
+
+    /// Mutably borrow the coat check.
+    ///
+    /// *Panics* if the coat check is currently borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, CoatCheck<V, T>> {
+        self.flush();
+        self.inner.cc.borrow_mut()
+    }
+
+    /// Check a value in and get a `Ticket` in exchange.
+    ///
+    /// *Panics* if the coat check is currently borrowed.
+    #[inline]
+    pub fn check(&self, value: V) -> T {
+        self.borrow_mut().check(value)
+    }
+
+    /// Check a value in and get a `Guard` that will claim it when dropped.
+    ///
+    /// *Panics* if the coat check is currently borrowed.
+    pub fn check_guarded(&self, value: V) -> Guard<V, T> {
+        Guard {
+            ticket: Some(self.check(value)),
+            cc: Rc::downgrade(&self.inner),
+        }
+    }
+
+    /// Claim an item.
+    ///
+    /// See `CoatCheck::claim` for details.
+    ///
+    /// *Panics* if the coat check is currently borrowed.
+    #[inline]
+    pub fn claim(&self, ticket: T) -> Result<V, ClaimError<T>> {
+        self.borrow_mut().claim(ticket)
+    }
+
+    /// Claim the values of guards dropped while the coat check was borrowed.
+    fn flush(&self) {
+        loop {
+            let tickets = mem::replace(&mut *self.inner.deferred.borrow_mut(), Vec::new());
+            if tickets.is_empty() {
+                return;
+            }
+            // Drop the values after releasing the coat check; they may hold guards themselves.
+            let values: Vec<V> = {
+                let mut cc = self.inner.cc.borrow_mut();
+                tickets.into_iter().filter_map(|t| cc.claim(t).ok()).collect()
+            };
+            drop(values);
+        }
+    }
+}
+
+impl<V, T> From<CoatCheck<V, T>> for SharedCoatCheck<V, T> {
+    #[inline]
+    fn from(cc: CoatCheck<V, T>) -> Self {
+        SharedCoatCheck {
+            inner: Rc::new(Shared {
+                cc: RefCell::new(cc),
+                deferred: RefCell::new(Vec::new()),
+            }),
+        }
+    }
+}
+
+impl<V, T> Clone for SharedCoatCheck<V, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        SharedCoatCheck { inner: self.inner.clone() }
+    }
+}
+
+impl<V, T> Default for SharedCoatCheck<V, T> where T: TicketType {
+    #[inline]
+    fn default() -> Self {
+        SharedCoatCheck::from(CoatCheck::typed())
+    }
+}
+
+impl<V, T> fmt::Debug for SharedCoatCheck<V, T> where V: fmt::Debug, T: TicketType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner.cc.try_borrow() {
+            Ok(cc) => fmt::Debug::fmt(&*cc, f),
+            Err(_) => write!(f, "<borrowed>"),
+        }
+    }
+}
+
+/// A ticket that claims (and drops) its value when dropped.
+///
+/// Guards don't keep their coat check alive. If the coat check is borrowed when the guard is
+/// dropped, the value is claimed the next time the coat check is mutably borrowed.
+#[must_use = "dropping the guard immediately claims the item"]
+pub struct Guard<V, T = Ticket> where T: TicketType {
+    ticket: Option<T>,
+    cc: Weak<Shared<V, T>>,
+}
+
+impl<V, T> Guard<V, T> where T: TicketType {
+    /// Borrow the guarded ticket (e.g., to look at the value).
+    #[inline]
+    pub fn ticket(&self) -> &T {
+        self.ticket.as_ref().unwrap()
+    }
+
+    /// Turn this guard back into a plain ticket, leaving the value checked in.
+    #[inline]
+    pub fn into_ticket(mut self) -> T {
+        self.ticket.take().unwrap()
+    }
+}
+
+impl<V, T> Drop for Guard<V, T> where T: TicketType {
+    fn drop(&mut self) {
+        let ticket = match self.ticket.take() {
+            Some(ticket) => ticket,
+            None => return,
+        };
+        let shared = match self.cc.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        // Bind the value so that it's dropped after releasing the coat check.
+        let _value = match shared.cc.try_borrow_mut() {
+            Ok(mut cc) => cc.claim(ticket).ok(),
+            Err(_) => {
+                shared.deferred.borrow_mut().push(ticket);
+                None
+            },
+        };
+    }
+}
+
+impl<V, T> fmt::Debug for Guard<V, T> where T: TicketType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Guard")
+    }
+}
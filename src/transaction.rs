@@ -0,0 +1,204 @@
+//! All-or-nothing batches of coat check operations.
+//!
+//! Inside of `CoatCheck::transaction`, checks and claims are staged and only applied once the
+//! transaction commits. Mutations (`Transaction::get_mut`) are applied immediately but the
+//! original values are saved so they can be restored if the transaction rolls back.
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+use {CoatCheck, Ticket, TicketType, Observer, ClaimError, AccessError, leak};
+
+/// A transaction on a `CoatCheck` (see `CoatCheck::transaction`).
+pub struct Transaction<'a, V, T = Ticket, O = ()> where V: 'a, T: 'a, O: 'a {
+    cc: &'a mut CoatCheck<V, T, O>,
+    /// Values to check in on commit.
+    checked: Vec<V>,
+    /// Tickets (and their indices) to claim on commit.
+    claimed: Vec<(T, usize)>,
+    /// Original values of mutated slots, by index.
+    undo: HashMap<usize, V>,
+    /// Where the transaction was started (recorded for the checked values).
+    caller: leak::Caller,
+}
+
+impl<'a, V, T, O> Transaction<'a, V, T, O> where T: TicketType, O: Observer<V> {
+    /// Stage a value to be checked in.
+    ///
+    /// Returns the position of the value's ticket in `Commit::tickets`.
+    #[inline]
+    pub fn check(&mut self, value: V) -> usize {
+        self.checked.push(value);
+        self.checked.len() - 1
+    }
+
+    /// Stage a claim.
+    ///
+    /// Returns `Err(ClaimError)` (immediately) if the ticket belongs to another `CoatCheck<V>` or
+    /// was issued before the coat check was last cleared (returning the ticket inside of the
+    /// ClaimError).
+    pub fn claim(&mut self, ticket: T) -> Result<(), ClaimError<T>> {
+        match self.cc.validate(ticket.as_ticket()) {
+            Ok(index) => {
+                self.claimed.push((ticket, index));
+                Ok(())
+            },
+            Err(kind) => Err(ClaimError { ticket: ticket, kind: kind }),
+        }
+    }
+
+    /// Get a reference to the value matching this ticket.
+    ///
+    /// See `CoatCheck::get` for details.
+    #[inline]
+    pub fn get(&self, ticket: &T) -> Result<&V, AccessError> {
+        self.cc.get(ticket)
+    }
+}
+
+impl<'a, V, T, O> Transaction<'a, V, T, O> where V: Clone, T: TicketType, O: Observer<V> {
+    /// Get a mutable reference to the value matching this ticket.
+    ///
+    /// The value is copied the first time it's mutated in a transaction so that it can be restored
+    /// if the transaction rolls back.
+    ///
+    /// See `CoatCheck::get_mut` for details.
+    pub fn get_mut(&mut self, ticket: &T) -> Result<&mut V, AccessError> {
+        let index = self.cc.validate(ticket.as_ticket()).map_err(|kind| AccessError { kind: kind })?;
+        if !self.undo.contains_key(&index) {
+            let original = self.cc.get(ticket)?.clone();
+            self.undo.insert(index, original);
+        }
+        self.cc.get_mut(ticket)
+    }
+}
+
+impl<'a, V, T, O> Transaction<'a, V, T, O> {
+    /// Restore the original values of all mutated slots.
+    fn restore(&mut self) {
+        for (index, value) in self.undo.drain() {
+            // Safe because we've validated the tickets used to mutate these slots and can't have
+            // claimed them yet.
+            match unsafe { self.cc.data.get_unchecked_mut(index) }.full_mut() {
+                Some(v) => *v = value,
+                None => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Restores the coat check if the transaction panics.
+impl<'a, V, T, O> Drop for Transaction<'a, V, T, O> {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+impl<'a, V, T, O> fmt::Debug for Transaction<'a, V, T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Transaction")
+    }
+}
+
+/// The result of a committed transaction.
+pub struct Commit<R, V, T = Ticket> {
+    /// The value returned by the transaction.
+    pub value: R,
+    /// The tickets for the checked values (in the order they were checked).
+    pub tickets: Vec<T>,
+    /// The claimed values (in the order they were claimed).
+    pub claimed: Vec<V>,
+}
+
+/// The result of a rolled back transaction.
+pub struct Rollback<E, V, T = Ticket> {
+    /// The error returned by the transaction.
+    pub error: E,
+    /// The values that would have been checked in (in the order they were checked).
+    pub values: Vec<V>,
+    /// The tickets that would have been claimed (in the order they were claimed).
+    pub tickets: Vec<T>,
+}
+
+impl<R, V, T> fmt::Debug for Commit<R, V, T> where R: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Commit({:?})", self.value)
+    }
+}
+
+impl<E, V, T> fmt::Debug for Rollback<E, V, T> where E: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rollback({:?})", self.error)
+    }
+}
+
+impl<V, T, O> CoatCheck<V, T, O> where T: TicketType, O: Observer<V> {
+    /// Run a transaction.
+    ///
+    /// If `f` returns `Ok`, all staged checks and claims are applied. Otherwise (or if `f`
+    /// panics), the coat check is left as it was: values staged for checking are handed back,
+    /// tickets staged for claiming are returned, and mutated values are restored.
+    ///
+    /// *Note:* If `f` panics, the staged values and tickets are dropped (leaking the values of the
+    /// dropped tickets).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coatcheck::CoatCheck;
+    ///
+    /// let mut cc = CoatCheck::new();
+    /// let old = cc.check("old");
+    ///
+    /// let commit = cc.transaction(|tx| {
+    ///     tx.check("new");
+    ///     tx.claim(old).map_err(|e| e.ticket)
+    /// }).unwrap();
+    /// assert_eq!(commit.claimed, vec!["old"]);
+    /// let new = commit.tickets.into_iter().next().unwrap();
+    ///
+    /// let rollback = cc.transaction(|tx| -> Result<(), &str> {
+    ///     tx.check("newer");
+    ///     tx.claim(new).unwrap();
+    ///     Err("oops")
+    /// }).unwrap_err();
+    /// assert_eq!(rollback.values, vec!["newer"]);
+    /// let new = rollback.tickets.into_iter().next().unwrap();
+    /// assert_eq!(cc.claim(new).unwrap(), "new");
+    /// ```
+    #[cfg_attr(feature = "leak-check", track_caller)]
+    pub fn transaction<F, R, E>(&mut self, f: F) -> Result<Commit<R, V, T>, Rollback<E, V, T>> where
+        F: FnOnce(&mut Transaction<V, T, O>) -> Result<R, E>
+    {
+        let mut tx = Transaction {
+            cc: self,
+            checked: Vec::new(),
+            claimed: Vec::new(),
+            undo: HashMap::new(),
+            caller: leak::caller(),
+        };
+        let result = f(&mut tx);
+        let checked = mem::replace(&mut tx.checked, Vec::new());
+        let claimed = mem::replace(&mut tx.claimed, Vec::new());
+        match result {
+            Ok(value) => {
+                tx.undo.clear();
+                let claimed = claimed.into_iter().map(|(ticket, index)| {
+                    ticket.into_ticket().consume();
+                    // Safe because we've validated the ticket.
+                    unsafe { tx.cc.remove(index) }
+                }).collect();
+                let tickets = checked.into_iter().map(|v| tx.cc.check_at(v, tx.caller)).collect();
+                Ok(Commit { value: value, tickets: tickets, claimed: claimed })
+            },
+            Err(error) => {
+                tx.restore();
+                Err(Rollback {
+                    error: error,
+                    values: checked,
+                    tickets: claimed.into_iter().map(|(ticket, _)| ticket).collect(),
+                })
+            },
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Coat checks with cheap checkpoints.
+//!
+//! A `VersionedCoatCheck<V>` stores its slots in reference counted chunks so taking a
+//! `Checkpoint` only bumps a reference count. Modifying the coat check afterwards copies the
+//! affected chunks (copy-on-write) and rolling back just swaps the chunks back in.
+//!
+//! Every check is stamped with a never-reused stamp (stored in both the slot and the ticket) so
+//! tickets for values checked in after a checkpoint can be told apart from tickets for values that
+//! were in the slot at checkpoint time.
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+use snowflake::ProcessUniqueId;
+
+use {Ticket, ClaimError, AccessError, ErrorKind};
+
+const CHUNK_BITS: usize = 5;
+const CHUNK_SIZE: usize = 1 << CHUNK_BITS;
+const CHUNK_MASK: usize = CHUNK_SIZE - 1;
+
+#[derive(Clone)]
+enum Slot<V> {
+    Empty(usize /* next free index */),
+    Full(u64 /* stamp */, V),
+}
+
+type Chunk<V> = Rc<Vec<Slot<V>>>;
+
+/// The (persistent) state of a `VersionedCoatCheck`.
+struct State<V> {
+    chunks: Rc<Vec<Chunk<V>>>,
+    len: usize,
+    next_free: usize,
+    size: usize,
+}
+
+impl<V> Clone for State<V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        State {
+            chunks: self.chunks.clone(),
+            len: self.len,
+            next_free: self.next_free,
+            size: self.size,
+        }
+    }
+}
+
+impl<V> State<V> where V: Clone {
+    #[inline]
+    fn slot(&self, index: usize) -> Option<&Slot<V>> {
+        if index < self.len {
+            Some(&self.chunks[index >> CHUNK_BITS][index & CHUNK_MASK])
+        } else {
+            None
+        }
+    }
+
+    /// Get a slot, copying it (and its chunk) if shared with a checkpoint.
+    #[inline]
+    fn slot_mut(&mut self, index: usize) -> &mut Slot<V> {
+        debug_assert!(index < self.len);
+        let chunk = &mut Rc::make_mut(&mut self.chunks)[index >> CHUNK_BITS];
+        &mut Rc::make_mut(chunk)[index & CHUNK_MASK]
+    }
+
+    fn push(&mut self, slot: Slot<V>) {
+        let chunks = Rc::make_mut(&mut self.chunks);
+        if self.len & CHUNK_MASK == 0 {
+            chunks.push(Rc::new(Vec::with_capacity(CHUNK_SIZE)));
+        }
+        Rc::make_mut(chunks.last_mut().unwrap()).push(slot);
+        self.len += 1;
+    }
+}
+
+/// A snapshot of a `VersionedCoatCheck` that it can later be rolled back to.
+///
+/// Taking a checkpoint is O(1).
+pub struct Checkpoint<V> {
+    tag: ProcessUniqueId,
+    state: State<V>,
+}
+
+impl<V> Clone for Checkpoint<V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Checkpoint { tag: self.tag, state: self.state.clone() }
+    }
+}
+
+impl<V> fmt::Debug for Checkpoint<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Checkpoint")
+    }
+}
+
+/// A coat check that can take cheap `Checkpoint`s and roll back to them.
+///
+/// # Examples
+///
+/// ```
+/// use coatcheck::VersionedCoatCheck;
+///
+/// let mut cc = VersionedCoatCheck::new();
+/// let a = cc.check("a");
+/// let checkpoint = cc.checkpoint();
+///
+/// let b = cc.check("b");
+/// assert_eq!(cc.claim(a).unwrap(), "a");
+///
+/// // Roll back: `b` goes stale and `a` is back (with a new ticket).
+/// let mut restored = cc.rollback(&checkpoint).unwrap();
+/// assert!(cc.get(&b).is_err());
+/// assert_eq!(cc.claim(restored.pop().unwrap()).unwrap(), "a");
+/// ```
+pub struct VersionedCoatCheck<V> {
+    tag: ProcessUniqueId,
+    state: State<V>,
+    /// The next check's stamp. Never rolled back.
+    next_stamp: u64,
+    /// Stamps of the values whose tickets have been turned in (and not reissued). Never rolled
+    /// back.
+    claimed: HashSet<u64>,
+}
+
+impl<V> VersionedCoatCheck<V> where V: Clone {
+    /// Constructs a new, empty `VersionedCoatCheck<V>`.
+    pub fn new() -> Self {
+        VersionedCoatCheck {
+            tag: ProcessUniqueId::new(),
+            state: State {
+                chunks: Rc::new(Vec::new()),
+                len: 0,
+                next_free: 0,
+                size: 0,
+            },
+            next_stamp: 0,
+            claimed: HashSet::new(),
+        }
+    }
+
+    /// The number of checked items.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.state.size
+    }
+
+    /// Check if this `VersionedCoatCheck<V>` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.state.size == 0
+    }
+
+    /// Check a value in and get a `Ticket` in exchange.
+    pub fn check(&mut self, value: V) -> Ticket {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        let index = self.state.next_free;
+        let slot = Slot::Full(stamp, value);
+        if index == self.state.len {
+            self.state.push(slot);
+            self.state.next_free = self.state.len;
+        } else {
+            match mem::replace(self.state.slot_mut(index), slot) {
+                Slot::Empty(next_free) => self.state.next_free = next_free,
+                Slot::Full(..) => panic!("expected no entry"),
+            }
+        }
+        self.state.size += 1;
+        // The ticket's epoch is the stamp of its check.
+        Ticket { tag: self.tag, epoch: stamp, index: index }
+    }
+
+    /// Check that a ticket refers to a value in this coat check, returning its index.
+    #[inline]
+    fn validate(&self, ticket: &Ticket) -> Result<usize, ErrorKind> {
+        if ticket.tag != self.tag {
+            return Err(ErrorKind::WrongCoatCheck);
+        }
+        match self.state.slot(ticket.index) {
+            Some(&Slot::Full(stamp, _)) if stamp == ticket.epoch => Ok(ticket.index),
+            _ => Err(ErrorKind::Stale),
+        }
+    }
+
+    /// Claim an item.
+    ///
+    /// Returns `Ok(value)` if the ticket refers to a value in this `VersionedCoatCheck<V>` (eating
+    /// the ticket).
+    /// Returns `Err(ClaimError)` with `ErrorKind::WrongCoatCheck` if the ticket belongs to another
+    /// coat check and `ErrorKind::Stale` if its value was checked in after the checkpoint this
+    /// coat check was last rolled back to (returning the ticket inside of the ClaimError).
+    pub fn claim(&mut self, ticket: Ticket) -> Result<V, ClaimError> {
+        match self.validate(&ticket) {
+            Ok(index) => {
+                let next_free = self.state.next_free;
+                let value = match mem::replace(self.state.slot_mut(index), Slot::Empty(next_free)) {
+                    Slot::Full(stamp, value) => {
+                        self.claimed.insert(stamp);
+                        value
+                    },
+                    Slot::Empty(_) => unreachable!(),
+                };
+                self.state.next_free = index;
+                self.state.size -= 1;
+                ticket.consume();
+                Ok(value)
+            },
+            Err(kind) => Err(ClaimError { ticket: ticket, kind: kind }),
+        }
+    }
+
+    /// Get a reference to the value matching this ticket.
+    ///
+    /// See `VersionedCoatCheck::claim` for the possible errors.
+    pub fn get(&self, ticket: &Ticket) -> Result<&V, AccessError> {
+        match self.validate(ticket) {
+            Ok(index) => match self.state.slot(index) {
+                Some(&Slot::Full(_, ref value)) => Ok(value),
+                _ => unreachable!(),
+            },
+            Err(kind) => Err(AccessError { kind: kind }),
+        }
+    }
+
+    /// Get a mutable reference to the value matching this ticket.
+    ///
+    /// This copies the value's chunk if it's shared with a checkpoint.
+    ///
+    /// See `VersionedCoatCheck::claim` for the possible errors.
+    pub fn get_mut(&mut self, ticket: &Ticket) -> Result<&mut V, AccessError> {
+        match self.validate(ticket) {
+            Ok(index) => match self.state.slot_mut(index) {
+                &mut Slot::Full(_, ref mut value) => Ok(value),
+                _ => unreachable!(),
+            },
+            Err(kind) => Err(AccessError { kind: kind }),
+        }
+    }
+
+    /// Take a checkpoint. This is O(1).
+    #[inline]
+    pub fn checkpoint(&self) -> Checkpoint<V> {
+        Checkpoint { tag: self.tag, state: self.state.clone() }
+    }
+
+    /// Roll back to a checkpoint.
+    ///
+    /// Tickets for values checked in after the checkpoint go stale. Values claimed after the
+    /// checkpoint are restored; as their tickets have been turned in, new tickets for them are
+    /// returned. Values whose tickets are still outstanding (e.g. values that went stale in an
+    /// earlier rollback) are restored without new tickets and their old tickets become valid again.
+    ///
+    /// Returns `Err(AccessError)` if the checkpoint was taken from another coat check.
+    pub fn rollback(&mut self, checkpoint: &Checkpoint<V>) -> Result<Vec<Ticket>, AccessError> {
+        if checkpoint.tag != self.tag {
+            return Err(AccessError { kind: ErrorKind::WrongCoatCheck });
+        }
+        let current = mem::replace(&mut self.state, checkpoint.state.clone());
+        let mut restored = Vec::new();
+        for (c, chunk) in self.state.chunks.iter().enumerate() {
+            let current_chunk = current.chunks.get(c);
+            // Unmodified chunks are still shared.
+            if current_chunk.map_or(false, |cur| Rc::ptr_eq(cur, chunk)) {
+                continue;
+            }
+            for (i, slot) in chunk.iter().enumerate() {
+                let stamp = match slot {
+                    &Slot::Full(stamp, _) => stamp,
+                    &Slot::Empty(_) => continue,
+                };
+                let still_checked = match current_chunk.and_then(|cur| cur.get(i)) {
+                    Some(&Slot::Full(s, _)) => s == stamp,
+                    _ => false,
+                };
+                if !still_checked && self.claimed.remove(&stamp) {
+                    restored.push(Ticket { tag: self.tag, epoch: stamp, index: (c << CHUNK_BITS) | i });
+                }
+            }
+        }
+        Ok(restored)
+    }
+}
+
+impl<V> Default for VersionedCoatCheck<V> where V: Clone {
+    #[inline]
+    fn default() -> Self {
+        VersionedCoatCheck::new()
+    }
+}
+
+impl<V> fmt::Debug for VersionedCoatCheck<V> where V: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        let mut first = true;
+        for chunk in self.state.chunks.iter() {
+            for slot in chunk.iter() {
+                if let &Slot::Full(_, ref v) = slot {
+                    if !first { write!(f, ", ")?; }
+                    first = false;
+                    write!(f, "{:?}", *v)?;
+                }
+            }
+        }
+        write!(f, "}}")
+    }
+}
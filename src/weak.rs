@@ -0,0 +1,278 @@
+//! Generational coat checks.
+//!
+//! A `WeakCoatCheck<V>` hands out the usual (unique) `Ticket`s but also lets ticket holders derive
+//! copyable `WeakTicket`s from them. A weak ticket can be used to look at a value for as long as
+//! its `Ticket` hasn't been claimed. Unlike a `Ticket`, a weak ticket can outlive its value so
+//! every slot carries a generation that is bumped whenever its value is claimed.
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use snowflake::ProcessUniqueId;
+
+use {CoatCheck, Ticket, ClaimError, AccessError, ErrorKind, Iter, IterMut, IntoIter};
+
+/// A copyable reference to a value checked into a `WeakCoatCheck<V>`.
+///
+/// Weak tickets can't be used to claim values and go stale as soon as the value they refer to has
+/// been claimed.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakTicket {
+    pub(crate) tag: ProcessUniqueId,
+    pub(crate) index: usize,
+    pub(crate) generation: u64,
+}
+
+impl fmt::Debug for WeakTicket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "WeakTicket")
+    }
+}
+
+/// A `CoatCheck<V>` that can hand out `WeakTicket`s.
+///
+/// # Examples
+///
+/// ```
+/// use coatcheck::{WeakCoatCheck, ErrorKind};
+///
+/// let mut cc = WeakCoatCheck::new();
+/// let ticket = cc.check("my value");
+///
+/// // Weak tickets can be freely copied...
+/// let weak = cc.weak(&ticket);
+/// let other = weak;
+/// assert_eq!(*cc.get_weak(&weak).unwrap(), "my value");
+/// assert_eq!(*cc.get_weak(&other).unwrap(), "my value");
+///
+/// // ...but go stale once the value is claimed.
+/// cc.claim(ticket).unwrap();
+/// let _ = cc.check("my other value");
+/// assert!(match cc.get_weak(&weak).unwrap_err().kind {
+///     ErrorKind::Stale => true,
+///     _ => false,
+/// });
+/// ```
+pub struct WeakCoatCheck<V> {
+    inner: CoatCheck<V>,
+    generations: Vec<u64>,
+}
+
+impl<V> WeakCoatCheck<V> {
+    /// Constructs a new, empty `WeakCoatCheck<T>`.
+    ///
+    /// The coat check will not allocate until elements are pushed onto it.
+    #[inline]
+    pub fn new() -> Self {
+        WeakCoatCheck::with_capacity(0)
+    }
+
+    /// Constructs a new, empty `WeakCoatCheck<T>` with the specified capacity.
+    ///
+    /// See `CoatCheck::with_capacity` for details.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        WeakCoatCheck {
+            inner: CoatCheck::with_capacity(capacity),
+            generations: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of elements the coat check can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// The number of checked items.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check if this `WeakCoatCheck<V>` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Reserves capacity for at least `additional` more elements to be checked into the given
+    /// `WeakCoatCheck<T>`.
+    ///
+    /// See `CoatCheck::reserve` for details.
+    pub fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+        let len = self.generations.len();
+        self.generations.reserve(self.inner.capacity() - len);
+    }
+
+    /// Check a value in and get a `Ticket` in exchange.
+    ///
+    /// *Panics* if the size of the `WeakCoatCheck<V>` would overflow `usize::MAX`.
+    pub fn check(&mut self, value: V) -> Ticket {
+        let ticket = self.inner.check(value);
+        if ticket.index == self.generations.len() {
+            self.generations.push(0);
+        }
+        ticket
+    }
+
+    /// Check if a ticket belongs to this `WeakCoatCheck<V>`.
+    #[inline]
+    pub fn contains_ticket(&self, ticket: &Ticket) -> bool {
+        self.inner.contains_ticket(ticket)
+    }
+
+    /// Claim an item.
+    ///
+    /// Claiming an item invalidates all `WeakTicket`s derived from its ticket.
+    ///
+    /// Returns `Ok(value)` if the ticket belongs to this `WeakCoatCheck<V>` (eating the ticket).
+    /// Returns `Err(ClaimError)` if the ticket belongs to another coat check (returning the ticket
+    /// inside of the ClaimError).
+    pub fn claim(&mut self, ticket: Ticket) -> Result<V, ClaimError> {
+        let index = ticket.index;
+        let value = self.inner.claim(ticket)?;
+        // Safe because, if we've handed out the ticket, this slot must exist.
+        let generation = unsafe { self.generations.get_unchecked_mut(index) };
+        *generation = generation.wrapping_add(1);
+        Ok(value)
+    }
+
+    /// Get a reference to the value matching this ticket.
+    ///
+    /// See `CoatCheck::get` for details.
+    #[inline]
+    pub fn get(&self, ticket: &Ticket) -> Result<&V, AccessError> {
+        self.inner.get(ticket)
+    }
+
+    /// Get a mutable reference to the value matching this ticket.
+    ///
+    /// See `CoatCheck::get_mut` for details.
+    #[inline]
+    pub fn get_mut(&mut self, ticket: &Ticket) -> Result<&mut V, AccessError> {
+        self.inner.get_mut(ticket)
+    }
+
+    /// Derive a `WeakTicket` from a ticket.
+    ///
+    /// Returns `Err(AccessError)` if the ticket belongs to another coat check.
+    pub fn try_weak(&self, ticket: &Ticket) -> Result<WeakTicket, AccessError> {
+        match self.inner.validate(ticket) {
+            Ok(index) => Ok(WeakTicket {
+                tag: ticket.tag,
+                index: index,
+                // Safe because, if we've handed out the ticket, this slot must exist.
+                generation: *unsafe { self.generations.get_unchecked(index) },
+            }),
+            Err(kind) => Err(AccessError { kind: kind }),
+        }
+    }
+
+    /// Derive a `WeakTicket` from a ticket.
+    ///
+    /// *Panics* if the ticket is invalid for this coat check (e.g., it belongs to another coat
+    /// check), with the reason as the message.
+    #[inline]
+    pub fn weak(&self, ticket: &Ticket) -> WeakTicket {
+        self.try_weak(ticket).unwrap_or_else(|e| panic!("{}", e.kind.description()))
+    }
+
+    /// Check if the value referenced by a weak ticket is still checked in.
+    ///
+    /// Returns `false` if the weak ticket belongs to another coat check or has gone stale.
+    #[inline]
+    pub fn is_live(&self, weak: &WeakTicket) -> bool {
+        self.get_weak(weak).is_ok()
+    }
+
+    /// Get a reference to the value matching this weak ticket.
+    ///
+    /// Returns `Ok(&value)` if the weak ticket belongs to this `WeakCoatCheck<V>` and its value
+    /// hasn't been claimed.
+    /// Returns `Err(AccessError)` with `ErrorKind::Stale` if its value has been claimed and
+    /// `ErrorKind::WrongCoatCheck` if the weak ticket belongs to another coat check.
+    pub fn get_weak(&self, weak: &WeakTicket) -> Result<&V, AccessError> {
+        let index = self.check_weak(weak)?;
+        // Safe because we've checked the generation (and the slot therefore exists).
+        Ok(unsafe { self.inner.data.get_unchecked(index) }.full_ref().expect("stale generation"))
+    }
+
+    /// Get a mutable reference to the value matching this weak ticket.
+    ///
+    /// See `WeakCoatCheck::get_weak` for details.
+    pub fn get_weak_mut(&mut self, weak: &WeakTicket) -> Result<&mut V, AccessError> {
+        let index = self.check_weak(weak)?;
+        // Safe because we've checked the generation (and the slot therefore exists).
+        Ok(unsafe { self.inner.data.get_unchecked_mut(index) }.full_mut().expect("stale generation"))
+    }
+
+    /// The tag of the tickets (and weak tickets) issued by this coat check.
+    #[inline]
+    pub(crate) fn tag(&self) -> ProcessUniqueId {
+        self.inner.tag
+    }
+
+    /// Validate a weak ticket returning its index.
+    #[inline]
+    fn check_weak(&self, weak: &WeakTicket) -> Result<usize, AccessError> {
+        if weak.tag != self.inner.tag {
+            return Err(AccessError { kind: ErrorKind::WrongCoatCheck });
+        }
+        // Safe because weak tickets are only derived from valid tickets.
+        if *unsafe { self.generations.get_unchecked(weak.index) } != weak.generation {
+            return Err(AccessError { kind: ErrorKind::Stale });
+        }
+        Ok(weak.index)
+    }
+
+    /// Iterate over the items in this `WeakCoatCheck<V>`.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, V> {
+        self.inner.iter()
+    }
+
+    /// Mutably iterate over the items in this `WeakCoatCheck<V>`.
+    #[inline]
+    pub fn iter_mut(&mut self) -> IterMut<'_, V> {
+        self.inner.iter_mut()
+    }
+}
+
+impl<V> IntoIterator for WeakCoatCheck<V> {
+    type Item = V;
+    type IntoIter = IntoIter<V>;
+
+    #[inline]
+    fn into_iter(self) -> IntoIter<V> {
+        self.inner.into_iter()
+    }
+}
+
+impl<V> fmt::Debug for WeakCoatCheck<V> where V: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<'a, V> Index<&'a Ticket> for WeakCoatCheck<V> {
+    type Output = V;
+    #[inline]
+    fn index(&self, ticket: &Ticket) -> &V {
+        &self.inner[ticket]
+    }
+}
+
+impl<'a, V> IndexMut<&'a Ticket> for WeakCoatCheck<V> {
+    #[inline]
+    fn index_mut(&mut self, ticket: &Ticket) -> &mut V {
+        &mut self.inner[ticket]
+    }
+}
+
+impl<V> Default for WeakCoatCheck<V> {
+    #[inline]
+    fn default() -> Self {
+        WeakCoatCheck::new()
+    }
+}
@@ -1,5 +1,6 @@
 #![feature(test)]
 
+#[macro_use]
 extern crate coatcheck;
 extern crate test;
 
@@ -78,3 +79,974 @@ fn check_all() {
     assert!(v.is_empty());
     assert!(cc.is_empty());
 }
+
+#[test]
+fn weak() {
+    let mut cc = WeakCoatCheck::new();
+    let t1 = cc.check(1);
+    let w1 = cc.weak(&t1);
+    let w2 = w1;
+    assert_eq!(*cc.get_weak(&w1).unwrap(), 1);
+    *cc.get_weak_mut(&w2).unwrap() = 2;
+    assert_eq!(cc[&t1], 2);
+
+    assert_eq!(cc.claim(t1).unwrap(), 2);
+    assert!(!cc.is_live(&w1));
+    let t2 = cc.check(3);
+    assert!(cc.get_weak(&w2).is_err());
+    assert!(cc.is_live(&cc.weak(&t2)));
+
+    let other: WeakCoatCheck<i32> = WeakCoatCheck::new();
+    assert!(other.get_weak(&cc.weak(&t2)).is_err());
+}
+
+#[test]
+fn concurrent() {
+    use std::sync::Arc;
+    use std::thread;
+
+    let cc = Arc::new(ConcurrentCoatCheck::new());
+    let threads: Vec<_> = (0..8usize).map(|n| {
+        let cc = cc.clone();
+        thread::spawn(move || {
+            let mut tickets = Vec::new();
+            for i in 0..1000 {
+                tickets.push(cc.check(n * 1000 + i));
+                if i % 3 == 0 {
+                    let t = tickets.swap_remove(i % tickets.len());
+                    let v = *cc.get(&t).unwrap();
+                    assert_eq!(cc.claim(t).unwrap(), v);
+                }
+            }
+            for (i, t) in tickets.iter_mut().enumerate() {
+                *cc.get_mut(t).unwrap() = i;
+            }
+            tickets
+        })
+    }).collect();
+    let tickets: Vec<Vec<Ticket>> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(cc.len(), tickets.iter().map(|t| t.len()).sum::<usize>());
+
+    let other = ConcurrentCoatCheck::<usize>::new();
+    for ts in tickets {
+        for (i, t) in ts.into_iter().enumerate() {
+            let t = Ticket::from(other.claim(t).unwrap_err());
+            assert_eq!(cc.claim(t).unwrap(), i);
+        }
+    }
+    assert!(cc.is_empty());
+}
+
+#[test]
+fn concurrent_drop() {
+    use std::rc::Rc;
+
+    let counter = Rc::new(());
+    {
+        let cc = ConcurrentCoatCheck::new();
+        let tickets: Vec<_> = (0..4).map(|_| cc.check(counter.clone())).collect();
+        let mut tickets = tickets.into_iter();
+        // Free a slot in the middle and one at the end (the end of the free list).
+        drop(cc.claim(tickets.next().unwrap()).unwrap());
+        let _kept = tickets.next().unwrap();
+        drop(cc.claim(tickets.next().unwrap()).unwrap());
+        assert_eq!(Rc::strong_count(&counter), 3);
+    }
+    // Only the checked values were dropped (and only once).
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+define_ticket!(struct Handle;);
+
+#[test]
+fn typed() {
+    let mut c1: CoatCheck<i32, Handle> = CoatCheck::typed();
+    let mut c2: CoatCheck<i32, Handle> = CoatCheck::default();
+    let h1: Handle = c1.check(1);
+    let hs: Vec<Handle> = c1.check_all(2..4).collect();
+    assert_eq!(c1.len(), 3);
+    assert!(c1.contains_ticket(&h1));
+    c1[&h1] += 10;
+    assert_eq!(c1[&h1], 11);
+
+    let h1 = Handle::from(c2.claim(h1).unwrap_err());
+    assert_eq!(c1.claim(h1).unwrap(), 11);
+    for (h, i) in hs.into_iter().zip(2..) {
+        assert_eq!(c1.claim(h).unwrap(), i);
+    }
+}
+
+#[test]
+fn branded() {
+    let values = CoatCheck::scope(|mut cc| {
+        let tickets: Vec<_> = (0..10).map(|i| cc.check(i)).collect();
+        for (i, t) in tickets.iter().enumerate() {
+            assert_eq!(cc[t], i);
+        }
+        let mut tickets = tickets.into_iter();
+        let t0 = tickets.next().unwrap();
+        assert_eq!(cc.claim(t0), 0);
+        let t = cc.check(100);
+        *cc.get_mut(&t) += 1;
+        assert_eq!(*cc.get(&t), 101);
+        assert_eq!(cc.len(), 10);
+        cc.iter().cloned().collect::<Vec<_>>()
+    });
+    assert_eq!(values, vec![101, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+}
+
+#[test]
+fn persist() {
+    use std::io::{Read, Write};
+
+    fn encode(v: &String, w: &mut dyn Write) -> std::io::Result<()> {
+        w.write_all(&(v.len() as u64).to_le_bytes())?;
+        w.write_all(v.as_bytes())
+    }
+    fn decode(r: &mut dyn Read) -> std::io::Result<String> {
+        let mut len = [0u8; 8];
+        r.read_exact(&mut len)?;
+        let mut buf = vec![0u8; u64::from_le_bytes(len) as usize];
+        r.read_exact(&mut buf)?;
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    let mut cc = CoatCheck::new();
+    let tickets: Vec<Ticket> = cc.check_all(vec!["a".to_string(), "b".to_string(), "c".to_string()].into_iter()).collect();
+    let mut tickets = tickets.into_iter();
+    let (ta, tb, tc) = (tickets.next().unwrap(), tickets.next().unwrap(), tickets.next().unwrap());
+    assert_eq!(cc.claim(tb).unwrap(), "b");
+    // The moved value's slot is freed when restoring.
+    let te = cc.check("e".to_string());
+    drop(cc.split_off(vec![&te]).unwrap());
+
+    let mut buf = Vec::new();
+    let snapshot = cc.save(&mut buf, encode).unwrap();
+    let ra = snapshot.receipt(&ta).unwrap();
+    let rc = Receipt::from_bytes(snapshot.receipt(&tc).unwrap().to_bytes());
+
+    let other = cc.save(&mut Vec::new(), encode).unwrap();
+    let (_, mut wrong) = CoatCheck::<String>::load(&buf[..], decode).unwrap();
+    assert!(wrong.redeem(other.receipt(&ta).unwrap()).is_err());
+
+    let (mut restored, mut redeemer) = CoatCheck::<String>::load(&buf[..], decode).unwrap();
+    assert_eq!(restored.len(), 2);
+    let ta2: Ticket = redeemer.redeem(ra).unwrap();
+    assert!(redeemer.redeem(ra).is_err());
+    let tc2: Ticket = redeemer.redeem(rc).unwrap();
+    let tb2 = restored.check("d".to_string());
+    assert_eq!(restored.claim(ta2).unwrap(), "a");
+    assert_eq!(restored.claim(tb2).unwrap(), "d");
+    assert_eq!(restored.claim(tc2).unwrap(), "c");
+
+    let last = buf.len() - 1;
+    buf[last] ^= 1;
+    assert!(CoatCheck::<String>::load(&buf[..], decode).is_err());
+}
+
+#[test]
+fn raw() {
+    let mut cc = CoatCheck::new();
+    let other: CoatCheck<i32> = CoatCheck::new();
+    let raw1 = cc.check(1).into_raw();
+    let raw2 = cc.check(2).into_raw();
+    assert_eq!(raw1.index(), 0);
+    let (epoch, index) = raw2.into_parts();
+    assert_eq!(index, 1);
+    assert!(RawTicket::from_parts(epoch, index) == raw2);
+
+    // `other` has nothing in that slot.
+    assert!(other.redeem_raw(raw1).is_err());
+    let t1: Ticket = cc.redeem_raw(raw1).unwrap();
+    assert_eq!(cc.claim(t1).unwrap(), 1);
+    assert!(cc.redeem_raw(raw1).is_err());
+
+    let t2: Ticket = unsafe { cc.redeem_raw_unchecked(raw2) };
+    assert_eq!(cc.claim(t2).unwrap(), 2);
+}
+
+#[test]
+fn clear() {
+    let mut cc = CoatCheck::new();
+    let t1 = cc.check(1);
+    let raw = cc.check(2).into_raw();
+    cc.clear();
+    assert!(cc.is_empty());
+    assert!(!cc.contains_ticket(&t1));
+    assert!(cc.get(&t1).is_err());
+    assert!(cc.redeem_raw(raw).is_err());
+
+    let t3 = cc.check(3);
+    let t4 = cc.check(4);
+    let t1 = match cc.claim(t1) {
+        Err(ClaimError { kind: ErrorKind::Expired, ticket }) => ticket,
+        _ => panic!("expected an expired ticket"),
+    };
+    assert_eq!(cc.claim(t3).unwrap(), 3);
+
+    let drained: Vec<i32> = cc.drain().collect();
+    assert_eq!(drained, vec![4]);
+    assert!(cc.claim(t4).is_err());
+    assert!(cc.claim(t1).is_err());
+    let t5 = cc.check(5);
+    assert_eq!(cc[&t5], 5);
+}
+
+#[test]
+#[should_panic(expected = "Ticket issued before the coat check was cleared")]
+fn index_expired() {
+    let mut cc = CoatCheck::new();
+    let ticket = cc.check(1);
+    cc.clear();
+    let _ = cc[&ticket];
+}
+
+#[cfg(feature = "leak-check")]
+#[test]
+fn leaks() {
+    let mut cc = CoatCheck::new();
+    let t1 = cc.check(1);
+    let line = line!() + 1;
+    drop(cc.check(2));
+    let _t3 = cc.check(3);
+    assert_eq!(cc.claim(t1).unwrap(), 1);
+    let _raw = cc.check(4).into_raw();
+
+    let leaks = cc.leaks();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(leaks[0].index, 1);
+    let location = leaks[0].location.unwrap();
+    assert_eq!(location.file(), file!());
+    assert_eq!(location.line(), line);
+
+    // Moved values aren't leaks (and their slots aren't handed to new values).
+    let (mut evens, _) = cc.split_off_by(|v| v % 2 == 0);
+    evens.clear();
+    let _t5 = cc.check(5);
+    let _t6 = cc.check(6);
+    assert!(cc.leaks().is_empty());
+
+    cc.clear();
+    assert!(cc.leaks().is_empty());
+}
+
+#[test]
+fn guards() {
+    let cc = SharedCoatCheck::new();
+    let g1 = cc.check_guarded(1);
+    let g2 = cc.check_guarded(2);
+    assert_eq!(cc.borrow()[g1.ticket()], 1);
+    drop(g1);
+    assert_eq!(cc.borrow().len(), 1);
+
+    let t2 = g2.into_ticket();
+    assert_eq!(cc.borrow().len(), 1);
+
+    // Dropped while borrowed: deferred until the next mutable borrow.
+    let g3 = cc.check_guarded(3);
+    {
+        let borrowed = cc.borrow();
+        drop(g3);
+        assert_eq!(borrowed.len(), 2);
+    }
+    assert_eq!(cc.borrow_mut().len(), 1);
+    assert_eq!(cc.claim(t2).unwrap(), 2);
+
+    // Guards don't keep the coat check alive.
+    let g4 = cc.check_guarded(4);
+    drop(cc);
+    drop(g4);
+}
+
+#[test]
+fn get_many_mut() {
+    let mut cc = CoatCheck::new();
+    let tickets: Vec<Ticket> = cc.check_all(0..4).collect();
+    {
+        let [a, b, c] = cc.get_many_mut([&tickets[2], &tickets[0], &tickets[3]]).unwrap();
+        *a += 10;
+        *b += 20;
+        *c += 30;
+    }
+    assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec![20, 1, 12, 33]);
+    assert!(cc.get_many_mut([&tickets[1], &tickets[1]]).is_err());
+
+    {
+        let refs: Vec<&Ticket> = tickets.iter().rev().collect();
+        for (i, v) in cc.get_many_mut_slice(&refs).unwrap().into_iter().enumerate() {
+            *v = i as i32;
+        }
+    }
+    assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec![3, 2, 1, 0]);
+    assert!(cc.get_many_mut_slice(&[&tickets[0], &tickets[3], &tickets[0]]).is_err());
+
+    let foreign = CoatCheck::new().check(0);
+    assert!(cc.get_many_mut([&tickets[0], &foreign]).is_err());
+}
+
+#[test]
+fn transfer() {
+    let mut c1 = CoatCheck::new();
+    let mut c2 = CoatCheck::new();
+    let mut c3 = CoatCheck::new();
+    let t1 = c1.check(1);
+    let _ = c2.check(0);
+
+    let t2 = c1.transfer(t1, &mut c2).unwrap();
+    assert!(c1.is_empty());
+    assert_eq!(c2.len(), 2);
+    assert_eq!(c2[&t2], 1);
+
+    // Belongs to neither.
+    let t2 = Ticket::from(c1.transfer(t2, &mut c3).unwrap_err());
+    assert!(c3.is_empty());
+    let t3 = c2.transfer(t2, &mut c3).unwrap();
+    assert_eq!(c3.claim(t3).unwrap(), 1);
+}
+
+#[test]
+fn absorb() {
+    let mut c1 = CoatCheck::new();
+    let mut c2 = CoatCheck::new();
+    let t1 = c1.check(1);
+    let t2: Vec<Ticket> = c2.check_all(2..6).collect();
+    let mut t2 = t2.into_iter();
+    let t2a = t2.next().unwrap();
+    let t2b = t2.next().unwrap();
+    assert_eq!(c2.claim(t2b).unwrap(), 3);
+
+    let mut remap = c1.absorb(c2);
+    assert_eq!(c1.len(), 4);
+    assert_eq!(remap.len(), 3);
+    let t2a = remap.exchange(t2a).unwrap();
+    assert_eq!(c1[&t2a], 2);
+    let rest: Vec<Ticket> = t2.map(|t| remap.exchange(t).unwrap()).collect();
+    assert!(remap.is_empty());
+    assert_eq!(rest.iter().map(|t| c1[t]).collect::<Vec<_>>(), vec![4, 5]);
+
+    let t1 = Ticket::from(remap.exchange(t1).unwrap_err());
+    assert_eq!(c1.claim(t1).unwrap(), 1);
+}
+
+#[test]
+fn split_off() {
+    let mut cc = CoatCheck::new();
+    let tickets: Vec<Ticket> = cc.check_all(0..6).collect();
+    let mut tickets = tickets.into_iter();
+    let t0 = tickets.next().unwrap();
+    let t1 = tickets.next().unwrap();
+    let t2 = tickets.next().unwrap();
+    let t3 = tickets.next().unwrap();
+
+    let (mut big, mut remap) = cc.split_off_by(|&v| v >= 4);
+    assert_eq!(cc.len(), 4);
+    assert_eq!(big.len(), 2);
+    let t4 = remap.exchange(tickets.next().unwrap()).unwrap();
+
+    // A ticket that hasn't been exchanged yet doesn't refer to a new value in the source.
+    let t5 = tickets.next().unwrap();
+    let t6 = cc.check(6);
+    assert!(!cc.contains_ticket(&t5));
+    assert!(cc.get(&t5).is_err());
+    let t5 = match cc.claim(t5) {
+        Err(ClaimError { kind: ErrorKind::Moved, ticket }) => ticket,
+        _ => panic!("expected ErrorKind::Moved"),
+    };
+    assert_eq!(cc.claim(t6).unwrap(), 6);
+    let t5 = remap.exchange(t5).unwrap();
+    assert_eq!(big[&t4], 4);
+    assert_eq!(big[&t5], 5);
+    let t0 = Ticket::from(remap.exchange(t0).unwrap_err());
+
+    let foreign = big.check(10);
+    assert!(cc.split_off(vec![&t1, &foreign]).is_err());
+    let (mut odd, mut remap) = cc.split_off(vec![&t1, &t3, &t1]).unwrap();
+    assert_eq!(cc.len(), 2);
+    assert!(!cc.contains_ticket(&t1));
+    assert!(cc.get(&t1).is_err());
+    let t1 = remap.exchange(t1).unwrap();
+    let t3 = remap.exchange(t3).unwrap();
+    assert_eq!(odd.claim(t1).unwrap(), 1);
+    assert_eq!(odd.claim(t3).unwrap(), 3);
+    assert_eq!(cc.claim(t0).unwrap(), 0);
+    assert_eq!(cc.claim(t2).unwrap(), 2);
+    assert_eq!(big.claim(foreign).unwrap(), 10);
+}
+
+#[test]
+fn clone_with_map() {
+    let mut cc = CoatCheck::new();
+    let t1 = cc.check(vec![1]);
+    let t2 = cc.check(vec![2]);
+    assert_eq!(cc.claim(t1).unwrap(), vec![1]);
+    let t3 = cc.check(vec![3]);
+
+    let (mut clone, mut map) = cc.clone_with_map();
+    assert_eq!(map.len(), 2);
+    let c2 = map.translate(&t2).unwrap();
+    assert!(map.translate(&t2).is_err());
+    let c3 = map.translate(&t3).unwrap();
+    assert!(map.is_empty());
+    assert!(map.translate(&c3).is_err());
+
+    clone[&c2].push(20);
+    assert_eq!(cc[&t2], vec![2]);
+    assert_eq!(clone.claim(c2).unwrap(), vec![2, 20]);
+    let c4 = clone.check(vec![4]);
+    assert_eq!(clone.len(), 2);
+    assert_eq!(clone.claim(c3).unwrap(), vec![3]);
+    assert_eq!(clone.claim(c4).unwrap(), vec![4]);
+    assert_eq!(cc.len(), 2);
+}
+
+#[test]
+fn versioned() {
+    let mut cc = VersionedCoatCheck::new();
+    let tickets: Vec<Ticket> = (0..100).map(|i| cc.check(i)).collect();
+    let mut tickets = tickets.into_iter();
+    let t0 = tickets.next().unwrap();
+    let t1 = tickets.next().unwrap();
+    let rest: Vec<Ticket> = tickets.collect();
+
+    let checkpoint = cc.checkpoint();
+    *cc.get_mut(&t0).unwrap() = 1000;
+    assert_eq!(cc.claim(t1).unwrap(), 1);
+    let t_new = cc.check(-1);
+    assert_eq!(*cc.get(&t_new).unwrap(), -1);
+    assert_eq!(cc.len(), 100);
+
+    let restored = cc.rollback(&checkpoint).unwrap();
+    assert_eq!(cc.len(), 100);
+    assert_eq!(*cc.get(&t0).unwrap(), 0);
+    assert!(cc.get(&t_new).is_err());
+    assert_eq!(restored.len(), 1);
+    let t_new = Ticket::from(cc.claim(t_new).unwrap_err());
+    let t1 = restored.into_iter().next().unwrap();
+    assert_eq!(*cc.get(&t1).unwrap(), 1);
+
+    // A second rollback doesn't hand out tickets that are still outstanding.
+    assert!(cc.rollback(&checkpoint).unwrap().is_empty());
+    // New checks don't revive stale tickets.
+    let t_newer = cc.check(-2);
+    assert!(cc.get(&t_new).is_err());
+    assert_eq!(cc.claim(t_newer).unwrap(), -2);
+
+    for (i, t) in rest.into_iter().enumerate() {
+        assert_eq!(cc.claim(t).unwrap(), i as i32 + 2);
+    }
+    assert_eq!(cc.claim(t1).unwrap(), 1);
+    assert_eq!(cc.claim(t0).unwrap(), 0);
+    assert!(cc.is_empty());
+
+    let other: VersionedCoatCheck<i32> = VersionedCoatCheck::new();
+    assert!(cc.rollback(&other.checkpoint()).is_err());
+}
+
+#[test]
+fn versioned_rollback_forward() {
+    let mut cc = VersionedCoatCheck::new();
+    let cp1 = cc.checkpoint();
+    let t = cc.check(5);
+    let cp2 = cc.checkpoint();
+
+    assert!(cc.rollback(&cp1).unwrap().is_empty());
+    assert!(cc.get(&t).is_err());
+    // `t` was never turned in so rolling forward again revives it instead of issuing a new ticket.
+    assert!(cc.rollback(&cp2).unwrap().is_empty());
+    assert_eq!(cc.len(), 1);
+    assert_eq!(cc.claim(t).unwrap(), 5);
+
+    // Once claimed, the value comes back with a new ticket (exactly once).
+    let mut restored = cc.rollback(&cp2).unwrap();
+    assert_eq!(restored.len(), 1);
+    assert!(cc.rollback(&cp2).unwrap().is_empty());
+    assert_eq!(cc.claim(restored.pop().unwrap()).unwrap(), 5);
+    assert!(cc.is_empty());
+}
+
+#[test]
+fn transaction() {
+    let mut cc = CoatCheck::new();
+    let a = cc.check(1);
+    let b = cc.check(2);
+
+    let rollback = cc.transaction(|tx| -> Result<(), ()> {
+        *tx.get_mut(&a).unwrap() = 10;
+        *tx.get_mut(&a).unwrap() = 20;
+        tx.check(3);
+        tx.claim(b).unwrap();
+        Err(())
+    }).unwrap_err();
+    assert_eq!(rollback.values, vec![3]);
+    assert_eq!(cc.len(), 2);
+    assert_eq!(cc[&a], 1);
+    let b = rollback.tickets.into_iter().next().unwrap();
+    assert_eq!(cc[&b], 2);
+
+    let commit = cc.transaction(|tx| -> Result<i32, ()> {
+        *tx.get_mut(&a).unwrap() = 10;
+        assert_eq!(tx.check(3), 0);
+        assert_eq!(tx.check(4), 1);
+        tx.claim(b).unwrap();
+        Ok(5)
+    }).unwrap();
+    assert_eq!(commit.value, 5);
+    assert_eq!(commit.claimed, vec![2]);
+    assert_eq!(cc.len(), 3);
+    assert_eq!(cc[&a], 10);
+    let mut tickets = commit.tickets.into_iter();
+    assert_eq!(cc.claim(tickets.next().unwrap()).unwrap(), 3);
+    assert_eq!(cc.claim(tickets.next().unwrap()).unwrap(), 4);
+
+    let other = CoatCheck::new().check(0);
+    let rollback = cc.transaction(|tx| tx.claim(other)).unwrap_err();
+    match rollback.error.kind {
+        ErrorKind::WrongCoatCheck => (),
+        _ => panic!("expected WrongCoatCheck"),
+    }
+}
+
+#[test]
+fn observer() {
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Log(RefCell<Vec<String>>);
+
+    impl Observer<i32> for Log {
+        fn on_check(&self, id: &TicketId, value: &i32) {
+            self.0.borrow_mut().push(format!("check {} {}", id.index(), value));
+        }
+        fn on_claim(&self, id: &TicketId, value: &i32) {
+            self.0.borrow_mut().push(format!("claim {} {}", id.index(), value));
+        }
+        fn on_access_error(&self, _: ErrorKind) {
+            self.0.borrow_mut().push("error".to_string());
+        }
+    }
+
+    let mut cc = CoatCheck::with_observer(Log::default());
+    let a = cc.check(1);
+    let b = cc.check(2);
+    assert_eq!(a.id().index(), 0);
+    assert!(a.id() != b.id());
+    assert_eq!(cc.claim(a).unwrap(), 1);
+    let mut other = CoatCheck::new();
+    let c = other.check(3);
+    assert!(cc.get(&c).is_err());
+    assert!(!cc.contains_ticket(&c));
+    let b = cc.transfer(b, &mut other).unwrap();
+    assert_eq!(other[&b], 2);
+    assert_eq!(*cc.observer().0.borrow(), vec![
+        "check 0 1", "check 1 2", "claim 0 1", "error", "claim 1 2",
+    ]);
+}
+
+#[test]
+fn registry() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use coatcheck::registry::{Registry, Handle};
+
+    let registry: Rc<Registry<()>> = Rc::new(Registry::new());
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    // Removes itself when fired.
+    let own: Rc<RefCell<Option<Handle>>> = Rc::new(RefCell::new(None));
+    let (r, l, o) = (registry.clone(), log.clone(), own.clone());
+    *own.borrow_mut() = Some(registry.add(move |_| {
+        l.borrow_mut().push("self");
+        r.remove(o.borrow_mut().take().unwrap()).unwrap();
+    }));
+
+    // Removes a callback that hasn't been called yet and registers a new one.
+    let victim: Rc<RefCell<Option<Handle>>> = Rc::new(RefCell::new(None));
+    let (r, l, v) = (registry.clone(), log.clone(), victim.clone());
+    let first = registry.add_with_priority(10, move |_| {
+        l.borrow_mut().push("first");
+        if let Some(handle) = v.borrow_mut().take() {
+            r.remove(handle).unwrap();
+            let l = l.clone();
+            let _h = r.add(move |_| l.borrow_mut().push("added"));
+        }
+    });
+    let l = log.clone();
+    *victim.borrow_mut() = Some(registry.add(move |_| l.borrow_mut().push("victim")));
+
+    let l = log.clone();
+    let once = registry.add_once_with_priority(5, move |_| l.borrow_mut().push("once"));
+    // Cancelled before it's called.
+    let l = log.clone();
+    let cancelled = registry.add_once(move |_| l.borrow_mut().push("cancelled"));
+    registry.remove(cancelled).unwrap();
+
+    registry.fire(&());
+    assert_eq!(*log.borrow(), vec!["first", "once", "self"]);
+    assert_eq!(registry.len(), 2);
+    // The one-shot callback is gone.
+    match registry.remove(once) {
+        Err(ClaimError { kind: ErrorKind::InvalidTicket, .. }) => (),
+        _ => panic!("expected InvalidTicket"),
+    }
+
+    log.borrow_mut().clear();
+    registry.fire(&());
+    assert_eq!(*log.borrow(), vec!["first", "added"]);
+
+    registry.remove(first).unwrap();
+    assert_eq!(registry.len(), 1);
+
+    // A spent one-shot handle doesn't remove a newer callback in the same slot.
+    let once = registry.add_once(|_| ());
+    registry.fire(&());
+    let l = log.clone();
+    let newer = registry.add(move |_| l.borrow_mut().push("newer"));
+    match registry.remove(once) {
+        Err(ClaimError { kind: ErrorKind::Stale, .. }) => (),
+        _ => panic!("expected Stale"),
+    }
+    log.borrow_mut().clear();
+    registry.fire(&());
+    assert_eq!(*log.borrow(), vec!["added", "newer"]);
+    registry.remove(newer).unwrap();
+}
+
+#[test]
+fn lend() {
+    let mut cc = CoatCheck::new();
+    let a = cc.check(1);
+    let b = cc.check(2);
+
+    let mut loan = cc.lend(&a).unwrap();
+    *loan += 10;
+    assert!(cc.lend(&a).is_err());
+    assert_eq!(cc.len(), 2);
+    assert_eq!(cc.iter().len(), 1);
+    assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec![2]);
+
+    let a = match cc.claim(a) {
+        Err(ClaimError { kind: ErrorKind::OnLoan, ticket }) => ticket,
+        _ => panic!("expected OnLoan"),
+    };
+
+    let mut other = CoatCheck::new();
+    let loan = match other.restore(loan) {
+        Err(ClaimError { kind: ErrorKind::WrongCoatCheck, ticket }) => ticket,
+        _ => panic!("expected WrongCoatCheck"),
+    };
+    {
+        // The clone can't get the loan back so it doesn't keep the slot.
+        let (mut clone, mut map) = cc.clone_with_map();
+        assert!(map.translate(&a).is_err());
+        assert_eq!(clone.len(), 1);
+        assert_eq!(clone.iter().len(), 1);
+        let c = clone.check(3);
+        assert_eq!(c.id().index(), a.id().index());
+        assert!(clone.save(Vec::new(), |_, _| Ok(())).is_ok());
+        clone.claim(c).unwrap();
+        clone.claim(map.translate(&b).unwrap()).unwrap();
+    }
+
+    cc.restore(loan).unwrap();
+    assert_eq!(cc.claim(a).unwrap(), 11);
+
+    let loan = cc.lend(&b).unwrap();
+    cc.clear();
+    assert!(cc.restore(loan).is_err());
+}
+
+#[test]
+fn ordered() {
+    let mut cc = OrderedCoatCheck::new();
+    let tickets: Vec<_> = (0..5).map(|i| cc.check(i)).collect();
+    let mut tickets = tickets.into_iter();
+    let t0 = tickets.next().unwrap();
+    let t1 = tickets.next().unwrap();
+    let t2 = tickets.next().unwrap();
+    let t3 = tickets.next().unwrap();
+    let t4 = tickets.next().unwrap();
+
+    assert_eq!(cc.claim(t2).unwrap(), 2);
+    assert_eq!(cc.claim(t0).unwrap(), 0);
+    assert_eq!(cc.claim(t4).unwrap(), 4);
+    // Reuse the freed slots.
+    let t5 = cc.check(5);
+    let t6 = cc.check(6);
+
+    assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 5, 6]);
+    assert_eq!(cc.iter().rev().cloned().collect::<Vec<_>>(), vec![6, 5, 3, 1]);
+    {
+        let mut iter = cc.iter();
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&6));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.clone().collect::<Vec<_>>(), vec![&3, &5]);
+    }
+    for v in cc.iter_mut() {
+        *v *= 10;
+    }
+    assert_eq!(cc[&t5], 50);
+    assert_eq!(cc.claim(t1).unwrap(), 10);
+    assert_eq!(cc.claim(t6).unwrap(), 60);
+    assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec![30, 50]);
+    assert!(cc.contains_ticket(&t3));
+    assert_eq!(cc.into_iter().rev().collect::<Vec<_>>(), vec![50, 30]);
+}
+
+#[test]
+fn secondary_map() {
+    let mut cc = WeakCoatCheck::new();
+    let mut map = SecondaryMap::new(&cc);
+    let a = cc.check("a");
+    let b = cc.check("b");
+    let (wa, wb) = (cc.weak(&a), cc.weak(&b));
+
+    assert_eq!(map.insert(&wa, 1).unwrap(), None);
+    assert_eq!(map.insert(&wa, 2).unwrap(), Some(1));
+    assert_eq!(map.insert(&wb, 3).unwrap(), None);
+    *map.get_mut(&wb).unwrap().unwrap() += 1;
+    assert_eq!(map.get(&wa).unwrap(), Some(&2));
+    assert_eq!(map.get(&wb).unwrap(), Some(&4));
+    assert_eq!(map.len(), 2);
+
+    let mut other = WeakCoatCheck::new();
+    let c = other.check("c");
+    let wc = other.weak(&c);
+    assert!(map.get(&wc).is_err());
+    assert!(map.insert(&wc, 5).is_err());
+
+    // Claim `a` and reuse its slot.
+    cc.claim(a).unwrap();
+    let d = cc.check("d");
+    let wd = cc.weak(&d);
+    assert_eq!(map.get(&wd).unwrap(), None);
+    assert_eq!(map.remove(&wd).unwrap(), None);
+    map.prune(&cc);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.insert(&wd, 6).unwrap(), None);
+    assert_eq!(map.get(&wa).unwrap(), None);
+
+    assert_eq!(map.remove(&wb).unwrap(), Some(4));
+    assert_eq!(map.get(&wb).unwrap(), None);
+    cc.claim(d).unwrap();
+    map.prune(&cc);
+    assert!(map.is_empty());
+}
+
+#[test]
+fn iter_with_ids() {
+    let mut cc = CoatCheck::new();
+    let t0 = cc.check(0);
+    let t1 = cc.check(1);
+    let t2 = cc.check(2);
+    let (id0, id1, id2) = (t0.id(), t1.id(), t2.id());
+    assert_eq!(cc.claim(t1).unwrap(), 1);
+    let t3 = cc.check(3);
+    let id3 = t3.id();
+    assert_eq!(id3.index(), id1.index());
+
+    assert_eq!(cc.iter_with_ids().collect::<Vec<_>>(), vec![(id0, &0), (id3, &3), (id2, &2)]);
+    {
+        let mut iter = cc.iter_with_ids();
+        assert_eq!(iter.next_back(), Some((id2, &2)));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.clone().next(), Some((id0, &0)));
+        assert_eq!(iter.next(), Some((id0, &0)));
+        assert_eq!(iter.next(), Some((id3, &3)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+    for (id, v) in cc.iter_mut_with_ids() {
+        *v += id.index() * 10;
+    }
+    assert_eq!(cc.iter().rev().cloned().collect::<Vec<_>>(), vec![22, 13, 0]);
+    assert_eq!(cc.iter().clone().count(), 3);
+
+    cc.claim(t0).unwrap();
+    drop((t2, t3));
+    let mut iter = cc.into_iter_with_ids();
+    assert_eq!(iter.clone().collect::<Vec<_>>(), vec![(id3, 13), (id2, 22)]);
+    assert_eq!(iter.next_back(), Some((id2, 22)));
+    assert_eq!(iter.collect::<Vec<_>>(), vec![(id3, 13)]);
+}
+
+#[test]
+fn check_with() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut cc = CoatCheck::new();
+    let a = cc.check_with(|id| (id, "a"));
+    assert!(cc[&a].0 == a.id());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        cc.check_with(|_| -> (TicketId, &str) { panic!("boom") })
+    }));
+    assert!(result.is_err());
+    assert_eq!(cc.len(), 1);
+    assert_eq!(cc.iter().count(), 1);
+
+    // The slot wasn't leaked.
+    let b = cc.check_with(|id| (id, "b"));
+    assert_eq!(b.id().index(), 1);
+    assert_eq!(cc.len(), 2);
+    assert_eq!(cc.claim(b).unwrap().1, "b");
+    assert_eq!(cc.claim(a).unwrap().1, "a");
+    assert!(cc.is_empty());
+}
+
+#[test]
+fn reserve_slot() {
+    let mut cc = CoatCheck::new();
+    let a = cc.check(1);
+    let (b, deposit) = cc.reserve_slot();
+    assert!(deposit.id() == b.id());
+    assert_eq!(cc.len(), 2);
+    assert_eq!(cc.iter().cloned().collect::<Vec<_>>(), vec![1]);
+
+    match cc.get(&b) {
+        Err(AccessError { kind: ErrorKind::Pending }) => (),
+        _ => panic!("expected Pending"),
+    }
+    let b = match cc.claim(b) {
+        Err(ClaimError { kind: ErrorKind::Pending, ticket }) => ticket,
+        _ => panic!("expected Pending"),
+    };
+
+    let mut other = CoatCheck::new();
+    let (deposit, value) = match deposit.fill(&mut other, 2) {
+        Err(ClaimError { kind: ErrorKind::WrongCoatCheck, ticket }) => ticket,
+        _ => panic!("expected WrongCoatCheck"),
+    };
+    deposit.fill(&mut cc, value).unwrap();
+    assert_eq!(cc.iter().len(), 2);
+    assert_eq!(cc.claim(b).unwrap(), 2);
+    assert_eq!(cc.claim(a).unwrap(), 1);
+
+    let (c, deposit) = cc.reserve_slot();
+    {
+        // The clone can't be filled so it doesn't keep the slot.
+        let (mut clone, _) = cc.clone_with_map();
+        assert!(clone.is_empty());
+        assert!(clone.save(Vec::new(), |_, _| Ok(())).is_ok());
+        let d = clone.check(4);
+        assert_eq!(d.id().index(), c.id().index());
+        clone.claim(d).unwrap();
+    }
+    cc.clear();
+    assert!(deposit.fill(&mut cc, 3).is_err());
+    assert!(cc.is_empty());
+}
+
+#[test]
+fn result_board() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+
+    struct Counter(AtomicUsize);
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let board = Arc::new(ResultBoard::new());
+
+    // Blocking.
+    let (ticket, promise) = board.reserve_slot();
+    assert!(promise.id() == ticket.id());
+    let ticket = match board.try_claim(ticket) {
+        Err(WaitError { kind: ErrorKind::Pending, ticket: Some(ticket) }) => ticket,
+        _ => panic!("expected Pending"),
+    };
+    let handle = thread::spawn(move || promise.fill(1));
+    assert_eq!(board.wait(ticket).unwrap(), 1);
+    handle.join().unwrap();
+
+    // Async.
+    let counter = Arc::new(Counter(AtomicUsize::new(0)));
+    let waker = Waker::from(counter.clone());
+    let mut cx = Context::from_waker(&waker);
+    let (ticket, promise) = board.reserve_slot();
+    let mut claim = board.claim_async(ticket);
+    assert!(Pin::new(&mut claim).poll(&mut cx).is_pending());
+    assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+    promise.fill(2);
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    // The future doesn't borrow the board.
+    let handle = thread::spawn(move || {
+        let waker = Waker::from(counter);
+        match Pin::new(&mut claim).poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(Ok(2)) => (),
+            _ => panic!("expected the result"),
+        }
+    });
+    handle.join().unwrap();
+
+    // Dropped futures don't leave their results behind.
+    let (ticket, promise) = board.reserve_slot();
+    let mut claim = board.claim_async(ticket);
+    assert!(Pin::new(&mut claim).poll(&mut cx).is_pending());
+    drop(claim);
+    promise.fill(3);
+    assert!(board.is_empty());
+    let (ticket, promise) = board.reserve_slot();
+    promise.fill(4);
+    drop(board.claim_async(ticket));
+    assert!(board.is_empty());
+
+    // Wrong board.
+    let other: ResultBoard<i32> = ResultBoard::new();
+    let (ticket, promise) = board.reserve_slot();
+    let ticket = match other.try_claim(ticket) {
+        Err(WaitError { kind: ErrorKind::WrongCoatCheck, ticket: Some(ticket) }) => ticket,
+        _ => panic!("expected WrongCoatCheck"),
+    };
+    promise.fill(3);
+    assert_eq!(board.try_claim(ticket).unwrap(), 3);
+    assert!(board.is_empty());
+}
+
+#[test]
+fn abandoned_result() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+
+    struct Noop;
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let board: Arc<ResultBoard<u32>> = Arc::new(ResultBoard::new());
+
+    // The worker panics before posting the result.
+    let (ticket, promise) = board.reserve_slot();
+    let handle = thread::spawn(move || {
+        let _promise = promise;
+        panic!("worker failed");
+    });
+    match board.wait(ticket) {
+        Err(WaitError { kind: ErrorKind::Abandoned, ticket: None }) => (),
+        _ => panic!("expected Abandoned"),
+    }
+    assert!(handle.join().is_err());
+    assert!(board.is_empty());
+
+    let (ticket, promise) = board.reserve_slot();
+    let mut claim = board.claim_async(ticket);
+    let waker = Waker::from(Arc::new(Noop));
+    let mut cx = Context::from_waker(&waker);
+    assert!(Pin::new(&mut claim).poll(&mut cx).is_pending());
+    drop(promise);
+    match Pin::new(&mut claim).poll(&mut cx) {
+        Poll::Ready(Err(WaitError { kind: ErrorKind::Abandoned, .. })) => (),
+        _ => panic!("expected Abandoned"),
+    }
+    assert!(board.is_empty());
+}